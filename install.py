@@ -26,3 +26,4 @@ if __name__ == '__main__':
       else:
         pass
     shutil.copytree("syntax", os.path.join(HOME, ".editrc/syntax"), dirs_exist_ok=True)
+    shutil.copytree("snippets", os.path.join(HOME, ".editrc/snippets"), dirs_exist_ok=True)
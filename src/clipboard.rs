@@ -0,0 +1,139 @@
+use std::io::{stdout, Write};
+use std::process::{Command, Stdio};
+use crossterm::{execute, style::Print};
+
+const BASE64_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// how text reaches the system clipboard; OSC 52 goes through the terminal
+// itself and therefore also works over ssh
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClipboardProvider {
+  Osc52,
+  Xclip,
+  WlCopy,
+  Pbcopy
+}
+
+impl ClipboardProvider {
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "osc52" => Some(ClipboardProvider::Osc52),
+      "xclip" => Some(ClipboardProvider::Xclip),
+      "wl-copy" => Some(ClipboardProvider::WlCopy),
+      "pbcopy" => Some(ClipboardProvider::Pbcopy),
+      "auto" => Some(Self::detect()),
+      _ => None
+    }
+  }
+
+  pub fn detect() -> Self {
+    if cfg!(target_os = "macos") {
+      ClipboardProvider::Pbcopy
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+      ClipboardProvider::WlCopy
+    } else if std::env::var_os("DISPLAY").is_some() {
+      ClipboardProvider::Xclip
+    } else {
+      ClipboardProvider::Osc52
+    }
+  }
+
+  fn copy_command(&self) -> Option<Command> {
+    let (program, args): (&str, &[&str]) = match self {
+      ClipboardProvider::Osc52 => return None,
+      ClipboardProvider::Xclip => ("xclip", &["-selection", "clipboard", "-in"]),
+      ClipboardProvider::WlCopy => ("wl-copy", &[]),
+      ClipboardProvider::Pbcopy => ("pbcopy", &[])
+    };
+    let mut command = Command::new(program);
+    command.args(args);
+    Some(command)
+  }
+
+  fn paste_command(&self) -> Option<Command> {
+    let (program, args): (&str, &[&str]) = match self {
+      ClipboardProvider::Osc52 => return None,
+      ClipboardProvider::Xclip => ("xclip", &["-selection", "clipboard", "-out"]),
+      ClipboardProvider::WlCopy => ("wl-paste", &["--no-newline"]),
+      ClipboardProvider::Pbcopy => ("pbpaste", &[])
+    };
+    let mut command = Command::new(program);
+    command.args(args);
+    Some(command)
+  }
+}
+
+#[derive(Debug)]
+pub struct Clipboard {
+  pub provider: ClipboardProvider
+}
+
+impl Clipboard {
+  pub fn new() -> Self {
+    Self {
+      provider: ClipboardProvider::Osc52
+    }
+  }
+
+  pub fn copy(&self, text: &str) {
+    if let Some(mut command) = self.provider.copy_command() {
+      if run_with_input(&mut command, text).is_ok() {
+        return
+      }
+    }
+    // external helper missing or failed, let the terminal handle it
+    let _ = execute!(
+      stdout(),
+      Print(format!("\x1b]52;c;{}\x07", base64_encode(text.as_bytes())))
+    );
+    let _ = stdout().flush();
+  }
+
+  // reading back through OSC 52 is rarely permitted by terminals,
+  // so only the external helpers can provide clipboard contents
+  pub fn paste(&self) -> Option<String> {
+    let output = self.provider.paste_command()?
+      .stdin(Stdio::null())
+      .stderr(Stdio::null())
+      .output()
+      .ok()?;
+    if output.status.success() {
+      String::from_utf8(output.stdout).ok()
+    } else {
+      None
+    }
+  }
+}
+
+fn run_with_input(command: &mut Command, input: &str) -> Result<(), std::io::Error> {
+  let mut child = command
+    .stdin(Stdio::piped())
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .spawn()?;
+  if let Some(stdin) = child.stdin.as_mut() {
+    stdin.write_all(input.as_bytes())?;
+  }
+  drop(child.stdin.take());
+  if child.wait()?.success() {
+    Ok(())
+  } else {
+    Err(std::io::Error::other("clipboard helper failed"))
+  }
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+  let mut encoded = String::new();
+  for chunk in bytes.chunks(3) {
+    let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+    let triple = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+    for idx in 0..4 {
+      if idx <= chunk.len() {
+        encoded.push(BASE64_TABLE[(triple >> (18 - 6 * idx) & 0x3f) as usize] as char)
+      } else {
+        encoded.push('=')
+      }
+    }
+  }
+  encoded
+}
@@ -0,0 +1,200 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::SystemTime;
+use dirs::home_dir;
+use crate::picker::{fuzzy_score, PickerMatch};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CandidateKind {
+  // a word found in an open buffer
+  Word,
+  Keyword,
+  Path
+}
+
+impl CandidateKind {
+  // shown dimmed beside the candidate
+  pub fn tag(&self) -> &'static str {
+    match self {
+      CandidateKind::Word => "word",
+      CandidateKind::Keyword => "keyword",
+      CandidateKind::Path => "path"
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+  pub text: String,
+  pub kind: CandidateKind
+}
+
+// the words and paths offered for the text before the cursor in Insert mode,
+// drawn as a list floating under it
+#[derive(Debug)]
+pub struct Popup {
+  pub row: usize,
+  // the column the completed text starts at, typing before it closes the popup
+  pub start: usize,
+  pub query: String,
+  candidates: Vec<Candidate>,
+  pub matches: Vec<PickerMatch>,
+  pub selected: usize
+}
+
+fn is_word(grapheme: &str) -> bool {
+  grapheme.chars().all(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+fn is_path(grapheme: &str) -> bool {
+  grapheme.chars().all(|ch| ch.is_alphanumeric() || matches!(ch, '_' | '-' | '.' | '/' | '~'))
+}
+
+// where the word that ends at 'column' starts
+pub fn word_start(graphemes: &[&str], column: usize) -> usize {
+  (0..column)
+    .rev()
+    .take_while(|idx| is_word(graphemes[*idx]))
+    .last()
+    .unwrap_or(column)
+}
+
+// where the text being completed starts: past the last slash of a path, with
+// the directory typed before it, otherwise at the start of the word
+pub fn prefix(graphemes: &[&str], column: usize) -> (usize, Option<String>) {
+  let path_start = (0..column)
+    .rev()
+    .take_while(|idx| is_path(graphemes[*idx]))
+    .last()
+    .unwrap_or(column);
+  match (path_start..column).rev().find(|idx| graphemes[*idx] == "/") {
+    Some(slash) => (slash + 1, Some(graphemes[path_start..=slash].concat())),
+    None => (word_start(graphemes, column), None)
+  }
+}
+
+// the words of 'lines' not in 'seen' yet, in the order they appear
+pub fn words<'a, I: Iterator<Item = &'a str>>(lines: I, seen: &mut HashSet<String>) -> Vec<Candidate> {
+  let mut found = Vec::new();
+  for line in lines {
+    let split = line.split(|ch: char| !(ch.is_alphanumeric() || ch == '_'));
+    for word in split.filter(|word| word.chars().count() > 1 && !word.starts_with(|ch: char| ch.is_ascii_digit())) {
+      if seen.insert(word.to_string()) {
+        found.push(Candidate { text: word.to_string(), kind: CandidateKind::Word })
+      }
+    }
+  }
+  found
+}
+
+// those of 'texts' not in 'seen' yet, as candidates of the kind given
+pub fn unseen<S: AsRef<str>>(texts: &[S], kind: CandidateKind, seen: &mut HashSet<String>) -> Vec<Candidate> {
+  texts
+    .iter()
+    .map(|text| text.as_ref())
+    .filter(|text| seen.insert(text.to_string()))
+    .map(|text| Candidate { text: text.to_string(), kind })
+    .collect()
+}
+
+// the words of files that are not open, each file read again only once it
+// has changed on disk
+#[derive(Debug, Default)]
+pub struct FileWords {
+  files: HashMap<String, (SystemTime, Vec<String>)>
+}
+
+impl FileWords {
+  pub fn get(&mut self, file: &str) -> &[String] {
+    let modified = match std::fs::metadata(file).and_then(|metadata| metadata.modified()) {
+      Ok(modified) => modified,
+      Err(_) => return &[]
+    };
+    if self.files.get(file).is_none_or(|(read, _)| *read != modified) {
+      let found = std::fs::read_to_string(file)
+        .map(|content| words(content.lines(), &mut HashSet::new()).into_iter().map(|word| word.text).collect())
+        .unwrap_or_default();
+      self.files.insert(file.to_string(), (modified, found));
+    }
+    &self.files[file].1
+  }
+}
+
+// the entries of the directory 'dir' as it was typed, such as "src/", "../"
+// or "~/", those that are directories ending in a slash
+pub fn paths(dir: &str) -> Vec<Candidate> {
+  let path = match dir.strip_prefix("~/") {
+    Some(rest) => match home_dir() {
+      Some(home) => home.join(rest),
+      None => return Vec::new()
+    },
+    None => PathBuf::from(dir)
+  };
+  let mut found: Vec<Candidate> = match std::fs::read_dir(path) {
+    Ok(entries) => entries
+      .flatten()
+      .map(|entry| {
+        let name = entry.file_name().to_string_lossy().to_string();
+        Candidate {
+          text: if entry.path().is_dir() { name + "/" } else { name },
+          kind: CandidateKind::Path
+        }
+      })
+      .collect(),
+    Err(_) => Vec::new()
+  };
+  found.sort_by(|first, second| first.text.cmp(&second.text));
+  found
+}
+
+impl Popup {
+  pub fn new(row: usize, start: usize, candidates: Vec<Candidate>) -> Self {
+    Self {
+      row,
+      start,
+      query: String::new(),
+      candidates,
+      matches: Vec::new(),
+      selected: 0
+    }
+  }
+
+  // ranks the candidates against what has been typed, those scoring the same
+  // keeping their order; what is typed already is not offered
+  pub fn refilter(&mut self, query: &str) {
+    self.query = query.to_string();
+    let mut matches: Vec<PickerMatch> = self.candidates
+      .iter()
+      .enumerate()
+      .filter(|(_, candidate)| candidate.text != query)
+      .filter_map(|(idx, candidate)| {
+        let (score, positions) = if query.is_empty() {
+          (0, Vec::new())
+        } else {
+          fuzzy_score(query, &candidate.text)?
+        };
+        Some(PickerMatch { item: idx, score, positions })
+      })
+      .collect();
+    matches.sort_by(|first, second| second.score.cmp(&first.score).then(first.item.cmp(&second.item)));
+    self.matches = matches;
+    self.selected = 0
+  }
+
+  pub fn candidate(&self, found: &PickerMatch) -> &Candidate {
+    &self.candidates[found.item]
+  }
+
+  pub fn selected_candidate(&self) -> Option<&Candidate> {
+    self.matches.get(self.selected).map(|found| self.candidate(found))
+  }
+
+  // moves through the list, wrapping around at either end
+  pub fn move_selection(&mut self, down: bool) {
+    let len = self.matches.len();
+    if len == 0 {
+      return
+    }
+    self.selected = if down { (self.selected + 1) % len } else { (self.selected + len - 1) % len }
+  }
+}
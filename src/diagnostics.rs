@@ -0,0 +1,142 @@
+use std::ops::Range;
+use crossterm::style::Color;
+use crate::marks;
+use crate::motion::Position;
+
+// ordered from the most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+  Error,
+  Warning,
+  Information,
+  Hint
+}
+
+impl Severity {
+  pub fn label(&self) -> &'static str {
+    match self {
+      Severity::Error => "error",
+      Severity::Warning => "warning",
+      Severity::Information => "info",
+      Severity::Hint => "hint"
+    }
+  }
+
+  // the letter shown in the gutter
+  pub fn sign(&self) -> char {
+    match self {
+      Severity::Error => 'E',
+      Severity::Warning => 'W',
+      Severity::Information => 'I',
+      Severity::Hint => 'H'
+    }
+  }
+
+  pub fn color(&self) -> Color {
+    match self {
+      Severity::Error => Color::Red,
+      Severity::Warning => Color::Yellow,
+      Severity::Information => Color::Blue,
+      Severity::Hint => Color::Cyan
+    }
+  }
+}
+
+// a problem reported about a document by a language server, a compiler or a
+// linter; positions are (row, column) in graphemes, the end exclusive
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+  pub start: Position,
+  pub end: Position,
+  pub severity: Severity,
+  pub message: String,
+  // what reported it, such as "lsp", which only replaces what it reported before
+  pub source: &'static str
+}
+
+// the diagnostics of one document, ordered by where they start
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+  items: Vec<Diagnostic>
+}
+
+impl Diagnostics {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.items.is_empty()
+  }
+
+  // replaces whatever 'source' reported before with 'diagnostics'
+  pub fn set(&mut self, source: &'static str, diagnostics: Vec<Diagnostic>) {
+    self.items.retain(|diagnostic| diagnostic.source != source);
+    self.items.extend(diagnostics);
+    self.items.sort_by(|first, second| first.start.cmp(&second.start).then(first.severity.cmp(&second.severity)))
+  }
+
+  // the most severe of those starting on 'row', for the gutter
+  pub fn severity(&self, row: usize) -> Option<Severity> {
+    self.items
+      .iter()
+      .filter(|diagnostic| diagnostic.start.0 == row)
+      .map(|diagnostic| diagnostic.severity)
+      .min()
+  }
+
+  // what to tell about 'pos': the most severe diagnostic under it, else the
+  // most severe starting on its row
+  pub fn at(&self, pos: Position) -> Option<&Diagnostic> {
+    let under = self.items
+      .iter()
+      .filter(|diagnostic| diagnostic.start <= pos && pos < diagnostic.end)
+      .min_by_key(|diagnostic| diagnostic.severity);
+    under.or_else(|| self.items
+      .iter()
+      .filter(|diagnostic| diagnostic.start.0 == pos.0)
+      .min_by_key(|diagnostic| diagnostic.severity))
+  }
+
+  // the column ranges of a row 'row_len' long to underline, each with the
+  // severity of what covers it; empty ranges are widened to a character
+  pub fn underlines(&self, row: usize, row_len: usize) -> Vec<(Range<usize>, Severity)> {
+    if row_len == 0 {
+      return Vec::new()
+    }
+    self.items
+      .iter()
+      .filter(|diagnostic| diagnostic.start.0 <= row && row <= diagnostic.end.0)
+      .map(|diagnostic| {
+        let start = if diagnostic.start.0 == row { std::cmp::min(diagnostic.start.1, row_len - 1) } else { 0 };
+        let end = if diagnostic.end.0 == row { std::cmp::min(diagnostic.end.1, row_len) } else { row_len };
+        (start..std::cmp::max(end, start + 1), diagnostic.severity)
+      })
+      .collect()
+  }
+
+  // where the next diagnostic after 'pos' starts (or the one before it when
+  // 'backward'), wrapping around the document
+  pub fn next(&self, pos: Position, backward: bool) -> Option<Position> {
+    let starts = self.items.iter().map(|diagnostic| diagnostic.start);
+    if backward {
+      starts.clone().rev().find(|start| *start < pos).or_else(|| starts.clone().next_back())
+    } else {
+      starts.clone().find(|start| *start > pos).or_else(|| starts.clone().next())
+    }
+  }
+
+  // keeps diagnostics on their rows once 'range' has been replaced by
+  // 'new_len' rows, dropping those that started on a removed row
+  pub fn adjust(&mut self, range: &Range<usize>, new_len: usize) {
+    self.items.retain_mut(|diagnostic| match marks::shift(diagnostic.start.0, range, new_len) {
+      Some(row) => {
+        let end = marks::shift(diagnostic.end.0, range, new_len).unwrap_or(row);
+        diagnostic.start.0 = row;
+        diagnostic.end.0 = std::cmp::max(end, row);
+        true
+      },
+      None => false
+    })
+  }
+}
@@ -1,5 +1,8 @@
 use std::io::{stdout, Write};
 use std::panic;
+use std::ops::Range;
+use std::path::Path;
+use std::collections::{HashMap, HashSet, VecDeque};
 use crossterm::{
   terminal::{
     enable_raw_mode,
@@ -10,14 +13,17 @@ use crossterm::{
   },
   event::{
     KeyCode, Event,
-    read, KeyEvent,
+    read, poll, KeyEvent,
     KeyModifiers,
+    EnableBracketedPaste,
+    DisableBracketedPaste,
   },
   style::{
     SetForegroundColor, SetBackgroundColor,
-    ResetColor,
+    SetUnderlineColor, ResetColor,
     Color,
-    Print, SetAttribute
+    Print, SetAttribute,
+    Attribute
   },
   cursor::{
     MoveTo, Hide, Show,
@@ -32,9 +38,64 @@ use crate::file::{
   IPositionDescriptor
 };
 use crate::history::*;
+use crate::keys;
+use crate::marks::{Location, Marks};
+use crate::registers::{Registers, RegisterContent};
+use crate::clipboard::{Clipboard, ClipboardProvider};
+use crate::motion::{self, CharClass, Motion, Position, Span};
+use crate::normal::{self, Action, Change, InsertAt, InsertKey, NormalCommand, Operator, Parsed, Target};
+use crate::textobject::{self, TextObject};
+use crate::substitute::{self, Address, LineRange, Substitute};
+use crate::search::{self, SearchOffset, SearchOptions, SearchPattern};
+use crate::quickfix::{Quickfix, QuickfixEntry};
+use crate::grep;
+use crate::replace::{self, FileChange};
+use crate::picker::{self, Picker, PickerItem, PickerKind};
+use crate::explorer::Explorer;
+use crate::recent;
+use crate::lsp::{self, CompletionItem, LspClient, LspLocation, LspReply, TextEdit};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::make::{self, Build, BuildError};
+use crate::completion::{self, Candidate, CandidateKind, FileWords, Popup};
+use crate::snippet::{self, Session};
+use crate::pairs;
+use crate::indent;
+use crate::highlighting::{self, Language, TokenKind};
 
 const NONE: KeyModifiers = KeyModifiers::empty();
 const UPPER: KeyModifiers = KeyModifiers::SHIFT;
+const MAX_REPLAY_DEPTH: usize = 100;
+const QUICKFIX_NAME: &str = "[Quickfix List]";
+const PREVIEW_NAME: &str = "[Replace Preview]";
+const MAX_RECENT_FILES: usize = 50;
+const MAX_POPUP_ROWS: usize = 8;
+// how long a word gets before the completion popup opens by itself
+const AUTOCOMPLETE_LENGTH: usize = 3;
+// listed by the command picker: what is put on the command line and what it does;
+// those ending in a space or delimiter wait for the rest to be typed
+const COMMANDS: &[(&str, &str)] = &[
+  (":q", "quit, writing the file if it changed"),
+  (":x", "quit without writing"),
+  (":set ", "set an option: ic, scs, literal, autocomplete, autopair, line, filename, clipboard or makeprg"),
+  (":s/", "substitute in the current line, %s/ for the whole file"),
+  (":noh", "stop highlighting search matches"),
+  (":grep ", "search the files under a directory into the quickfix list"),
+  (":make", "run the build in the background, its errors into the quickfix list"),
+  (":copen", "show the quickfix list"),
+  (":cnext", "go to the next quickfix entry"),
+  (":cprev", "go to the previous quickfix entry"),
+  (":cc ", "go to a quickfix entry by number"),
+  (":cdo s/", "substitute on the quickfix lines, after a preview"),
+  (":cfdo s/", "substitute in the quickfix files, after a preview"),
+  (":Explore", "list the directory of the open file"),
+  (":Files", "pick a file under the working directory"),
+  (":Buffers", "pick a file opened during this session"),
+  (":History", "pick a recently opened file"),
+  (":Symbols", "pick a function defined in the open file"),
+  (":Commands", "pick a command"),
+  (":Rename ", "rename the symbol under the cursor with the language server"),
+  (":Diagnostics", "list the language server diagnostics in the quickfix list")
+];
 #[allow(dead_code)]
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -42,7 +103,8 @@ macro_rules! char_key {
   ($key: pat) => {
     Event::Key(KeyEvent {
       code: KeyCode::Char($key),
-      modifiers: NONE
+      modifiers: NONE,
+      ..
     })
   };
 }
@@ -51,7 +113,18 @@ macro_rules! char_upper_key {
   ($key: pat) => {
     Event::Key(KeyEvent {
       code: KeyCode::Char($key),
-      modifiers: UPPER
+      modifiers: UPPER,
+      ..
+    })
+  };
+}
+
+macro_rules! ctrl_key {
+  ($key: pat) => {
+    Event::Key(KeyEvent {
+      code: KeyCode::Char($key),
+      modifiers: KeyModifiers::CONTROL,
+      ..
     })
   };
 }
@@ -60,7 +133,8 @@ macro_rules! special_key {
   ($en_t: pat) => {
     Event::Key(KeyEvent {
       code: $en_t,
-      modifiers: NONE
+      modifiers: NONE,
+      ..
     })
   };
 }
@@ -77,7 +151,8 @@ impl Terminal {
     enable_raw_mode()?;
     execute!(
       stdout(),
-      EnterAlternateScreen
+      EnterAlternateScreen,
+      EnableBracketedPaste
     )?;
     Ok(Self {
       height: dimensions.1,
@@ -108,16 +183,16 @@ pub enum EditorMode {
   Normal,
   Command,
   Insert,
-  Search
+  Picker
 }
 
-impl EditorMode {
-  pub fn to_string(&self) -> String {
-    String::from(match self {
+impl std::fmt::Display for EditorMode {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(match self {
       EditorMode::Normal => "VIEW",
       EditorMode::Command => "COMMAND",
       EditorMode::Insert => "INSERT",
-      EditorMode::Search => "SEARCH"
+      EditorMode::Picker => "PICK"
     })
   }
 }
@@ -136,6 +211,11 @@ pub struct StatusBar {
   pub cmd: String,
   pub cmd_chars: usize,
   pub mode: EditorMode,
+  pub recording: Option<char>,
+  // shown in place of the command line until the next key
+  pub message: String,
+  // the diagnostic at the cursor, shown when there is nothing else to show
+  pub diagnostic: String,
 }
 
 impl StatusBar {
@@ -147,6 +227,9 @@ impl StatusBar {
       cmd: String::new(),
       cmd_chars: 0,
       mode: EditorMode::Normal,
+      recording: None,
+      message: String::new(),
+      diagnostic: String::new(),
     }
   }
 
@@ -166,12 +249,29 @@ impl StatusBar {
 
   pub fn render(&mut self, current: usize, total: usize) {
     self.terminal_size = size().unwrap();
-    let mode_str = self.mode.to_string();
+    let mode_str = if let Some(register) = self.recording {
+      format!("recording @{} {}", register, self.mode)
+    } else {
+      self.mode.to_string()
+    };
     let line_chars = current.to_string().chars().count() + total.to_string().chars().count();
     let mut stdout = stdout();
+    let room = (self.terminal_size.0 as usize)
+      .saturating_sub(mode_str.len())
+      .saturating_sub(line_chars)
+      .saturating_sub(4);
+    // a diagnostic can run long, it gives way to the rest of the bar
+    let diagnostic: String = self.diagnostic.chars().take(room).collect();
+    let (text, text_chars) = if !self.cmd.is_empty() {
+      (&self.cmd, self.cmd_chars)
+    } else if !self.message.is_empty() {
+      (&self.message, self.message.chars().count())
+    } else {
+      (&diagnostic, diagnostic.chars().count())
+    };
     let content = format!("{}{}{}",
-      self.cmd,
-      (self.cmd_chars..(self.terminal_size.0 as usize - mode_str.len() - line_chars - 4))
+      text,
+      (text_chars..room)
         .map(|_| " ")
         .collect::<String>(),
       mode_str);
@@ -193,13 +293,72 @@ pub struct Editor {
   pub mode: EditorMode,
   pub status_bar: StatusBar,
   pub history: History,
-  pub search_results: Option<std::vec::IntoIter<(usize, usize)>>,
+  // undo histories of the files that are not open, by name
+  histories: HashMap<String, History>,
+  // files left with changes that are not written yet, by name, shown again
+  // when they are next opened and written on quitting
+  modified: HashMap<String, Document>,
+  pub registers: Registers,
+  pub clipboard: Clipboard,
+  pub marks: Marks,
+  pub quickfix: Quickfix,
+  // matches of the search as (row, byte range), all drawn highlighted;
+  // found again when the pattern, the options or the document change
+  search_matches: Vec<(usize, Range<usize>)>,
+  // the pattern, options and document version the highlighted matches are of
+  matches_of: Option<(String, SearchOptions, u64)>,
+  last_search: Option<SearchPattern>,
+  highlight_search: bool,
+  search_options: SearchOptions,
+  // whether the open document is the quickfix list
+  showing_quickfix: bool,
+  // where the cursor was when a search pattern started being typed
+  search_origin: Option<(usize, usize)>,
+  // (row, byte range) pairs drawn highlighted
+  marked: Vec<(usize, Range<usize>)>,
+  // the overlay shown in Picker mode
+  picker: Option<Picker>,
+  // the directory listed by the open document, if it is a listing
+  explorer: Option<Explorer>,
+  // files opened during the session, the latest first
+  recent_files: Vec<String>,
+  // the words completion offers from the files among them that aren't open
+  file_words: FileWords,
+  // files opened in this and earlier sessions, by absolute path
+  old_files: Vec<String>,
+  // a language server per language, started when a file of it is first opened
+  servers: Vec<LspClient>,
+  // languages whose server could not be started, which is not tried again
+  failed_servers: Vec<Language>,
+  // what the completion picker offers, by index
+  completions: Vec<CompletionItem>,
+  // words and paths offered while typing, opened with Ctrl-N or Ctrl-P
+  popup: Option<Popup>,
+  // whether typing a word opens the popup without asking
+  autocomplete: bool,
+  // whether brackets and quotes are typed in pairs
+  autopair: bool,
+  // the fields of the snippet expanded last, while Tab moves through them
+  snippet: Option<Session>,
+  // the :make running in the background
+  build: Option<Build>,
+  // what :make runs, set with 'set makeprg', otherwise guessed
+  make_program: Option<String>,
+  // what the last build reported, shown as diagnostics in the files it names
+  build_errors: Vec<BuildError>,
+  pending_events: VecDeque<Event>,
+  // register being recorded into and the keys typed so far
+  recording: Option<(char, String)>,
+  last_replayed: Option<char>,
+  replay_depth: usize,
+  normal_keys: Vec<char>,
+  last_change: Option<Change>,
+  // the change whose Insert session is still being typed
+  pending_change: Option<Change>,
   _old_position: (u16, u16),
   altered: bool,
   view_frame: (usize, usize),
   _quit: bool,
-  _search_current: usize,
-  _search_total: usize,
   position: (u16, u16),
   buffer: u16
 }
@@ -210,6 +369,7 @@ impl Editor {
       let _ = execute!(
         stdout(),
         // LeaveAlternateScreen,
+        DisableBracketedPaste,
         ResetColor,
         SetCursorShape(CursorShape::Block),
         EnableBlinking
@@ -218,7 +378,12 @@ impl Editor {
     }));
     let terminal = Terminal::new()?;
     let terminal_rows = terminal.size().1;
-    Ok(Editor {
+    // a directory is listed once the editor is set up, instead of opened
+    let (dir_name, file_name) = match file_name {
+      Some(name) if Path::new(name).is_dir() => (Some(name), None),
+      name => (None, name)
+    };
+    let mut editor = Editor {
       terminal,
       altered: false,
       file: if let Some(file_name) = file_name {
@@ -235,11 +400,53 @@ impl Editor {
       position: (0, 0),
       buffer: 0,
       history: History::new(),
-      search_results: None,
-      _search_current: 0,
-      _search_total: 0,
+      histories: HashMap::new(),
+      modified: HashMap::new(),
+      registers: Registers::new(),
+      clipboard: Clipboard::new(),
+      marks: Marks::new(),
+      quickfix: Quickfix::new(),
+      search_matches: Vec::new(),
+      matches_of: None,
+      last_search: None,
+      highlight_search: false,
+      search_options: SearchOptions::default(),
+      showing_quickfix: false,
+      search_origin: None,
+      marked: Vec::new(),
+      picker: None,
+      explorer: None,
+      recent_files: file_name.into_iter().cloned().collect(),
+      file_words: FileWords::default(),
+      old_files: recent::load(),
+      servers: Vec::new(),
+      failed_servers: Vec::new(),
+      completions: Vec::new(),
+      popup: None,
+      autocomplete: false,
+      autopair: true,
+      snippet: None,
+      build: None,
+      make_program: None,
+      build_errors: Vec::new(),
+      pending_events: VecDeque::new(),
+      recording: None,
+      last_replayed: None,
+      replay_depth: 0,
+      normal_keys: Vec::new(),
+      last_change: None,
+      pending_change: None,
       _old_position: position()?,
-    })
+    };
+    if let Some(dir_name) = dir_name {
+      editor.explore(dir_name, None);
+    } else {
+      editor.attach_lsp()
+    }
+    if let Some(file_name) = file_name {
+      recent::remember(&mut editor.old_files, file_name)
+    }
+    Ok(editor)
   }
 
   pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
@@ -259,56 +466,145 @@ impl Editor {
       self.terminal.set_dimensions();
       self.view_frame = (old_view_frame.0, old_view_frame.1 + self.terminal.height as usize - old_num_rows);
       self.render();
+      self.dispatch();
+      std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+  }
+
+  fn dispatch(&mut self) {
+    let history_len = self.history.len();
+    // language servers and builds are listened to for as long as no key is pressed
+    if (self.servers.iter().any(LspClient::is_running) || self.build.is_some())
+      && self.pending_events.is_empty()
+      && !poll(std::time::Duration::from_millis(50)).unwrap_or(false) {
+      self.receive_lsp();
+      self.receive_build();
+      self.receive_picker()
+    } else {
+      self.status_bar.message.clear();
       match &self.mode {
         EditorMode::Normal => self.handle_normal(),
         EditorMode::Command => self.handle_command(),
         EditorMode::Insert => self.handle_insert(),
-        EditorMode::Search => self.handle_search()
+        EditorMode::Picker => self.handle_picker()
       }
-      std::thread::sleep(std::time::Duration::from_millis(1));
     }
+    for (range, new_len) in self.file.take_row_edits() {
+      self.file.diagnostics.adjust(&range, new_len);
+      self.marks.adjust(&self.file.file_name, range, new_len)
+    }
+    if self.history.len() > history_len {
+      self.marks.record_change(&self.file.file_name, self.cursor())
+    }
+    self.sync_lsp()
   }
 
-  fn write_row(&self, row_no: usize, offset: usize, row: &Row) {
-    let mut printed_string = String::from(row.content());
+  // 'marked' are column ranges of the row drawn highlighted, such as search matches;
+  // diagnostics are underlined and the most severe on the row signed in the gutter
+  // 'bracket' is the column of the bracket matching the one under the cursor
+  fn write_row(&self, row_no: usize, offset: usize, row: &Row, marked: &[Range<usize>], bracket: Option<usize>) {
+    let marked: Vec<Range<usize>> = marked
+      .iter()
+      .map(|columns| row.byte_index(columns.start)..row.byte_index(columns.end))
+      .collect();
+    let bracket = bracket.map(|column| row.byte_index(column)..row.byte_index(column + 1));
+    let underlined: Vec<(Range<usize>, Severity)> = self.file.diagnostics
+      .underlines(row_no - 1, row.len())
+      .into_iter()
+      .map(|(columns, severity)| (row.byte_index(columns.start)..row.byte_index(columns.end), severity))
+      .collect();
+    let width = (self.terminal.width - self.buffer - 1) as usize;
     let mut current_written = 0;
-    printed_string.truncate((self.terminal.width - self.buffer - 1) as usize);
     let mut stdout = stdout();
-    execute!(
-      stdout,
-      Print(format!("{:indent$}{} ", "", row_no, indent=offset))
-    ).unwrap();
-    if let Some(highlighted_rows) = self.file.highlighted_rows() {
-      for token in highlighted_rows.get(row_no - 1).unwrap() {
-        current_written += token.get_original().chars().count(); // temporary
-        if current_written > (self.terminal.width - self.buffer - 1) as usize { // temporary
-          break
+    match self.file.diagnostics.severity(row_no - 1) {
+      Some(severity) => execute!(
+        stdout,
+        SetForegroundColor(severity.color()),
+        Print(severity.sign()),
+        ResetColor,
+        Print(format!("{:indent$}{} ", "", row_no, indent=offset.saturating_sub(1)))
+      ).unwrap(),
+      None => execute!(
+        stdout,
+        Print(format!("{:indent$}{} ", "", row_no, indent=offset))
+      ).unwrap()
+    }
+    let pieces: Vec<(&str, Option<&Color>, &Attribute)> = match self.file.highlighted_rows() {
+      Some(highlighted_rows) => highlighted_rows.get(row_no - 1).unwrap()
+        .iter()
+        .map(|token| {
+          let (color, attribute) = token.get_color_and_attribute();
+          (token.get_original(), color, attribute)
+        })
+        .collect(),
+      None => vec![(row.content(), None, &Attribute::Reset)]
+    };
+    let mut piece_start = 0;
+    for (text, color, attribute) in pieces {
+      // split the piece wherever a marked range begins or ends
+      let mut cuts = vec![0, text.len()];
+      for range in marked.iter().chain(underlined.iter().map(|(range, _)| range)).chain(bracket.iter()) {
+        for cut in [range.start, range.end] {
+          if cut > piece_start && cut < piece_start + text.len() {
+            cuts.push(cut - piece_start)
+          }
         }
-        if let (Some(color), attribute) = token.get_color_and_attribute() {
+      }
+      cuts.sort_unstable();
+      cuts.dedup();
+      for cut in cuts.windows(2) {
+        let part: String = text[cut[0]..cut[1]].chars().take(width.saturating_sub(current_written)).collect();
+        current_written += part.chars().count();
+        let is_marked = marked.iter().any(|range| range.contains(&(piece_start + cut[0])));
+        let underline = underlined
+          .iter()
+          .filter(|(range, _)| range.contains(&(piece_start + cut[0])))
+          .map(|(_, severity)| *severity)
+          .min();
+        if is_marked {
+          execute!(
+            stdout,
+            SetBackgroundColor(Color::Yellow),
+            SetForegroundColor(Color::Black)
+          ).unwrap();
+        } else if bracket.as_ref().is_some_and(|range| range.contains(&(piece_start + cut[0]))) {
+          execute!(
+            stdout,
+            SetBackgroundColor(Color::DarkCyan),
+            SetForegroundColor(Color::White)
+          ).unwrap();
+        } else if let Some(color) = color {
           execute!(
             stdout,
             SetForegroundColor(*color),
-            SetAttribute(*attribute),
-            Print(format!("{}", token.get_original())),
-            ResetColor
+            SetAttribute(*attribute)
           ).unwrap();
-        } else {
+        }
+        if let Some(severity) = underline {
+          execute!(
+            stdout,
+            SetAttribute(Attribute::Underlined),
+            SetUnderlineColor(severity.color())
+          ).unwrap();
+        }
+        execute!(
+          stdout,
+          Print(part),
+          ResetColor
+        ).unwrap();
+        if underline.is_some() {
           execute!(
             stdout,
-            Print(format!("{}", token.get_original()))
+            SetAttribute(Attribute::NoUnderline)
           ).unwrap();
         }
       }
-      execute!(
-        stdout,
-        Print("\r\n")
-      ).unwrap();
-    } else {
-      execute!(
-        stdout,
-        Print(format!("{}\r\n", printed_string))
-      ).unwrap();
+      piece_start += text.len();
     }
+    execute!(
+      stdout,
+      Print("\r\n")
+    ).unwrap();
   }
 
   fn write_empty_line(&self) {
@@ -319,8 +615,17 @@ impl Editor {
     ).unwrap();
   }
 
+  // the gutter holds the line numbers, after a column of diagnostic signs
+  // while the document has any
   fn set_buffer(&mut self) {
-    self.buffer = self.file.len().to_string().chars().count() as u16;
+    let signs = if self.file.diagnostics.is_empty() { 0 } else { 1 };
+    let buffer = self.file.len().to_string().chars().count() as u16 + signs;
+    // the cursor stays on its character when the gutter changes width
+    if buffer != self.buffer && self.position.1 > self.buffer {
+      let column = self.position.1 - self.buffer;
+      self.move_to(buffer + column, self.position.0)
+    }
+    self.buffer = buffer
   }
 
   fn move_to(&mut self, column: u16, row: u16) {
@@ -333,58 +638,405 @@ impl Editor {
   }
 
   fn handle_command(&mut self) {
-    match read().unwrap() {
+    match self.read_event() {
       char_key!(key) => {
         self.status_bar.add_command(key);
+        self.incremental_search()
       },
       char_upper_key!(key) => {
-        self.status_bar.add_command(key)
+        self.status_bar.add_command(key);
+        self.incremental_search()
       },
       special_key!(KeyCode::Enter) => {
         self.move_to(self.position.1, self.position.0);
-        if let Ok(_) = self.evaluate_expr() {
+        // commands such as :Files switch to a mode of their own
+        if self.evaluate_expr().is_ok() && self.mode == EditorMode::Command {
           self.set_mode(EditorMode::Normal)
         }
       },
       special_key!(KeyCode::Esc) => {
         self.set_mode(EditorMode::Normal);
         self.status_bar.cmd.clear();
-        self.status_bar.cmd_chars = 0
+        self.status_bar.cmd_chars = 0;
+        self.incremental_search()
       },
       special_key!(KeyCode::Backspace) => {
         if self.status_bar.cmd.len() > 1 {
-          self.status_bar.remove_command()
+          self.status_bar.remove_command();
+          self.incremental_search()
         }
-      }
+      },
+      Event::Paste(text) => {
+        // the command line is a single line, keep only what fits on it
+        for key in text.chars().take_while(|key| *key != '\n' && *key != '\r') {
+          self.status_bar.add_command(key)
+        }
+        self.incremental_search()
+      },
       _ => ()
     }
   }
 
   fn handle_insert(&mut self) {
-    match read().unwrap() {
-      char_key!(key) | char_upper_key!(key) => self.insert(key),
+    let event = self.read_event();
+    // the completion popup takes the keys that move through it and pick from it
+    if let Some(popup) = &mut self.popup {
+      match event {
+        ctrl_key!('n') | special_key!(KeyCode::Down) => return popup.move_selection(true),
+        ctrl_key!('p') | special_key!(KeyCode::Up) => return popup.move_selection(false),
+        special_key!(KeyCode::Enter) | special_key!(KeyCode::Tab) => return self.accept_completion(),
+        special_key!(KeyCode::Esc) => {
+          self.popup = None;
+          return
+        },
+        _ => ()
+      }
+    }
+    let key = match event {
+      char_key!(key) | char_upper_key!(key) => InsertKey::Char(key),
       special_key!(KeyCode::Tab) => {
+        if self.snippet.is_some() {
+          return self.next_snippet_field(false)
+        }
+        if self.expand_snippet() {
+          return
+        }
+        InsertKey::Tab
+      },
+      Event::Key(KeyEvent { code: KeyCode::BackTab, .. }) if self.snippet.is_some() => return self.next_snippet_field(true),
+      special_key!(KeyCode::Backspace) => InsertKey::Backspace,
+      ctrl_key!(' ') => {
+        self.lsp_request(|client, pos| client.completion(pos));
+        return
+      },
+      // Ctrl-P starts from the bottom of the list
+      ctrl_key!(key @ ('n' | 'p')) => {
+        self.open_completion(true);
+        if let Some(popup) = self.popup.as_mut().filter(|_| key == 'p') {
+          popup.move_selection(false)
+        }
+        return
+      },
+      special_key!(KeyCode::Enter) => InsertKey::Newline,
+      Event::Paste(text) => InsertKey::Text(text),
+      special_key!(KeyCode::Esc) => {
+        if let Some(change) = self.pending_change.take() {
+          if change.count > 1 {
+            self.as_single_edit(|editor| {
+              for _ in 1..change.count {
+                editor.retype(&change, true)
+              }
+            })
+          }
+          self.last_change = Some(change)
+        }
+        self.set_mode(EditorMode::Normal);
+        return
+      },
+      event => {
+        // moving around ends what '.' will repeat of this session
+        if let Some(change) = self.pending_change.take() {
+          self.last_change = Some(change)
+        }
+        self.popup = None;
+        match event {
+          special_key!(KeyCode::Down) => self.scroll(Direction::Down),
+          special_key!(KeyCode::Up) => self.scroll(Direction::Up),
+          special_key!(KeyCode::Left) => self.scroll(Direction::Left),
+          special_key!(KeyCode::Right) => self.scroll(Direction::Right),
+          _ => () // TODO: all the insert operations, refreshing the buffer
+        }
+        return
+      }
+    };
+    if let Some(change) = &mut self.pending_change {
+      change.inserted.push(key.clone())
+    }
+    let typed = if let InsertKey::Char(key) = key { Some(key) } else { None };
+    if self.snippet.is_some() {
+      self.insert_in_snippet(key)
+    } else {
+      self.insert_key(key)
+    }
+    self.altered = true;
+    self.update_completion(typed)
+  }
+
+  // Tab after the name of one of the language's snippets replaces the name
+  // with the snippet, as a single change, and goes to its first field
+  fn expand_snippet(&mut self) -> bool {
+    let language = match self.file.language() {
+      Some(language) => language,
+      None => return false
+    };
+    let (row, column) = self.cursor();
+    let start = completion::word_start(&motion::graphemes(&self.file, row), column);
+    if start == column {
+      return false
+    }
+    let name = motion::graphemes(&self.file, row)[start..column].concat();
+    let body = match snippet::load(language).remove(&name) {
+      Some(body) => body,
+      None => return false
+    };
+    let indent: String = self.file.rows[row].content().chars().take_while(|ch| ch.is_whitespace()).collect();
+    let expansion = snippet::expand(&body, &indent);
+    self.as_single_edit(|editor| editor.complete_with(start, &expansion.text));
+    // moving to the fields ends what '.' repeats of this session
+    if let Some(change) = self.pending_change.take() {
+      self.last_change = Some(change)
+    }
+    self.snippet = Session::start(&expansion, (row, start));
+    self.visit_snippet_field();
+    true
+  }
+
+  // Tab and Shift-Tab, to the next or previous field; past the last one, or
+  // once at $0, the snippet is done with
+  fn next_snippet_field(&mut self, backward: bool) {
+    if let Some(change) = self.pending_change.take() {
+      self.last_change = Some(change)
+    }
+    let session = match &mut self.snippet {
+      Some(session) => session,
+      None => return
+    };
+    match session.next(backward) {
+      Some(number) => {
+        session.current = number;
+        session.fresh = true;
+        self.visit_snippet_field()
+      },
+      None if !backward => self.snippet = None,
+      None => ()
+    }
+  }
+
+  // puts the cursor after the current field's placeholder
+  fn visit_snippet_field(&mut self) {
+    let field = match self.snippet.as_ref().and_then(Session::field) {
+      Some(field) => field.clone(),
+      None => return
+    };
+    self.jump_to(field.row, field.column + field.len);
+    if field.number == 0 {
+      self.snippet = None
+    }
+  }
+
+  // a key typed in a snippet's field: over a fresh placeholder it replaces
+  // it, and what the field then holds is copied wherever else its number
+  // appears; typing outside the field, or a new line, ends the snippet
+  fn insert_in_snippet(&mut self, key: InsertKey) {
+    let (row, column) = self.cursor();
+    let field = self.snippet.as_ref().and_then(Session::field).cloned();
+    let fresh = self.snippet.as_ref().is_some_and(|session| session.fresh) && field.as_ref().is_some_and(|field| field.len > 0);
+    let field = match field {
+      Some(field) if field.row == row
+        && (field.column..=(field.column + field.len)).contains(&column)
+        && !(key == InsertKey::Backspace && column == field.column && !fresh)
+        && !matches!(&key, InsertKey::Newline | InsertKey::Tab)
+        && !matches!(&key, InsertKey::Text(text) if text.contains('\n')) => field,
+      _ => {
+        self.snippet = None;
+        return self.insert_key(key)
+      }
+    };
+    let before = self.file.rows[row].len();
+    if fresh {
+      self.jump_to(row, field.column + field.len);
+      for _ in 0..field.len {
+        self.delete()
+      }
+    }
+    if !(fresh && key == InsertKey::Backspace) {
+      self.insert_key(key)
+    }
+    let delta = self.file.rows[row].len() as isize - before as isize;
+    if let Some(session) = &mut self.snippet {
+      session.fresh = false;
+      if let Some(current) = session.fields.iter_mut().find(|other| other.number == field.number) {
+        current.len = (current.len as isize + delta) as usize
+      }
+      session.shift(row, field.column, delta)
+    }
+    self.mirror_snippet_field()
+  }
+
+  // copies what the current field holds to its other appearances
+  fn mirror_snippet_field(&mut self) {
+    let field = match self.snippet.as_ref().and_then(Session::field) {
+      Some(field) => field.clone(),
+      None => return
+    };
+    let text = self.file.rows[field.row].slice(field.column..(field.column + field.len)).to_string();
+    let mirrors: Vec<usize> = self.snippet.as_ref().unwrap().fields
+      .iter()
+      .enumerate()
+      .filter(|(_, other)| other.number == field.number)
+      .map(|(idx, _)| idx)
+      .skip(1)
+      .collect();
+    for idx in mirrors {
+      let mirror = self.snippet.as_ref().unwrap().fields[idx].clone();
+      let (row, column) = (mirror.row, mirror.column);
+      if self.file.rows.get(row).is_none_or(|other| other.len() < column + mirror.len) {
+        continue
+      }
+      let (cursor_row, cursor_column) = self.cursor();
+      self.edit_rows(row..(row + 1), |file| {
+        file.delete_text((row, column), (row, column + mirror.len));
+        file.insert_text((row, column), &text);
+      });
+      let delta = field.len as isize - mirror.len as isize;
+      if let Some(session) = &mut self.snippet {
+        session.fields[idx].len = field.len;
+        session.shift(row, column, delta)
+      }
+      if cursor_row == row && cursor_column > column {
+        self.jump_to(cursor_row, (cursor_column as isize + delta) as usize)
+      }
+    }
+  }
+
+  fn insert_key(&mut self, key: InsertKey) {
+    match key {
+      InsertKey::Char(key) => {
+        let (row, column) = self.cursor();
+        // a key that changes how the row is indented does so in the same change
+        if indent::electric(self.file.language(), self.file.rows[row].slice(0..column), key) {
+          self.as_single_edit(|editor| {
+            if !editor.type_pair(key) {
+              editor.insert(key)
+            }
+            editor.reindent_row(row)
+          })
+        } else if !self.type_pair(key) {
+          self.insert(key)
+        }
+      },
+      InsertKey::Tab => {
         self.insert(' ');
         self.insert(' ') // yeah i'm forcing you to use 2 space tabs
       },
-      special_key!(KeyCode::Backspace) => self.delete(),
-      special_key!(KeyCode::Enter) => self.insert_row(),
-      special_key!(KeyCode::Esc) => self.set_mode(EditorMode::Normal),
-      special_key!(KeyCode::Down) => self.scroll(Direction::Down),
-      special_key!(KeyCode::Up) => self.scroll(Direction::Up),
-      special_key!(KeyCode::Left) => self.scroll(Direction::Left),
-      special_key!(KeyCode::Right) => self.scroll(Direction::Right),
-      _ => () // TODO: all the insert operations, refreshing the buffer
+      InsertKey::Newline => self.insert_row(),
+      InsertKey::Backspace => {
+        if !self.delete_pair() {
+          self.delete()
+        }
+      },
+      InsertKey::Text(text) => self.paste_text(&text)
     }
-    self.altered = true;
+  }
+
+  // the brackets and quotes typed in pairs here, none with 'set noautopair'
+  fn pairs(&self) -> Vec<(char, char)> {
+    if !self.autopair {
+      return Vec::new()
+    }
+    pairs::pairs(self.file.language(), self.file.syntax_file.as_ref())
+  }
+
+  // whether 'pos' is in a string or a comment, where nothing is paired: the
+  // character before it is in one and is not the quote that closes it
+  fn in_literal(&self, pos: Position) -> bool {
+    let idx = self.file.rows[pos.0].byte_index(pos.1);
+    let tokens = match self.file.row_tokens(pos.0) {
+      Some(tokens) if idx > 0 => tokens,
+      _ => return false
+    };
+    let closed = tokens.iter().any(|token| token.range.end == idx && matches!(token.kind, TokenKind::String | TokenKind::Char));
+    highlighting::in_literal(&tokens, idx - 1) && !closed
+  }
+
+  // an opening bracket or quote typed together with its closing half, as a
+  // single change, or a closing half typed over the one already there
+  fn type_pair(&mut self, key: char) -> bool {
+    let pairs = self.pairs();
+    let (row, column) = self.cursor();
+    let graphemes = motion::graphemes(&self.file, row);
+    let before = graphemes[..column].concat();
+    let next = graphemes.get(column).map(|next| next.to_string());
+    if next == Some(key.to_string()) && pairs.iter().any(|(_, close)| *close == key) {
+      self.jump_to(row, column + 1);
+      return true
+    }
+    let close = match pairs.iter().find(|(open, _)| *open == key) {
+      Some((_, close)) => *close,
+      None => return false
+    };
+    // nothing is paired in front of a word, nor a quote behind one
+    let word_next = next.is_some_and(|next| next.chars().any(|ch| ch.is_alphanumeric() || ch == '_'));
+    if word_next || (key == close && !pairs::opens_string(&before)) || self.in_literal((row, column)) {
+      return false
+    }
+    self.edit_rows(row..(row + 1), |file| {
+      file.insert_text((row, column), &format!("{}{}", key, close));
+    });
+    self.jump_to(row, column + 1);
+    true
+  }
+
+  // Backspace between the halves of an empty pair deletes both
+  fn delete_pair(&mut self) -> bool {
+    let (row, column) = self.cursor();
+    if column == 0 {
+      return false
+    }
+    let graphemes = motion::graphemes(&self.file, row);
+    let (previous, next) = (graphemes[column - 1].to_string(), graphemes.get(column).map(|next| next.to_string()));
+    let empty = self.pairs()
+      .iter()
+      .any(|(open, close)| previous == open.to_string() && next == Some(close.to_string()));
+    if !empty {
+      return false
+    }
+    self.edit_rows(row..(row + 1), |file| {
+      file.delete_text((row, column - 1), (row, column + 1));
+    });
+    self.jump_to(row, column - 1);
+    true
   }
 
   fn handle_normal(&mut self) {
-    match read().unwrap() {
-      char_key!('i') => self.set_mode(EditorMode::Insert),
-      char_key!(':') => {
-        self.set_mode(EditorMode::Command);
-        self.status_bar.add_command(':');
+    let event = self.read_event();
+    match event {
+      Event::Paste(text) => self.paste_text(&text),
+      char_key!('q') if self.recording.is_some() && self.normal_keys.is_empty() => self.stop_recording(),
+      char_key!(key @ ('-' | '%' | 'd' | 'R' | 'D')) | char_upper_key!(key @ ('%' | 'R' | 'D'))
+        if self.explorer.is_some() && self.normal_keys.is_empty() => self.explorer_command(key),
+      char_key!(key) | char_upper_key!(key) => {
+        self.normal_keys.push(key);
+        match normal::parse(&self.normal_keys) {
+          Parsed::Pending => (),
+          Parsed::Invalid => self.normal_keys.clear(),
+          Parsed::Complete(command) => {
+            self.normal_keys.clear();
+            self.execute_normal(command)
+          }
+        }
+      },
+      special_key!(KeyCode::Esc) => {
+        if self.normal_keys.is_empty() {
+          self.highlight_search = false
+        }
+        self.normal_keys.clear()
+      },
+      special_key!(KeyCode::Enter) if self.explorer.is_some() => self.open_explorer_entry(),
+      special_key!(KeyCode::Enter) if self.showing_quickfix => {
+        let entry = self.quickfix.select(self.cursor().0).cloned();
+        self.open_quickfix_entry(entry)
+      },
+      // Ctrl-I arrives as a Tab
+      ctrl_key!('o') | special_key!(KeyCode::Tab) => {
+        let count = self.normal_keys.iter().collect::<String>().parse().ok();
+        self.normal_keys.clear();
+        let action = if let ctrl_key!('o') = event { Action::JumpOlder } else { Action::JumpNewer };
+        self.execute_normal(NormalCommand::new(count, action))
+      },
+      ctrl_key!('p') => {
+        self.normal_keys.clear();
+        self.open_file_picker()
       },
       special_key!(KeyCode::Down) => {
         self.scroll(Direction::Down)
@@ -402,29 +1054,27 @@ impl Editor {
     }
   }
 
-  fn handle_search(&mut self) {
-    match read().unwrap() {
-      special_key!(KeyCode::Esc) => self.set_mode(EditorMode::Normal),
-      special_key!(KeyCode::Enter) => {
-        if let Some(search_results) = &mut self.search_results {
-          if let Some((row_idx, row_offset)) = search_results.next() {
-            self._search_current += 1;
-            self.goto_line(row_idx + 1);
-            self.move_to(row_offset as u16 + self.buffer + 1, self.position.0)
-          } else {
-            self.set_mode(EditorMode::Normal)
-          }
-        } else {
-          self.set_mode(EditorMode::Normal)
-        }
-      },
-      char_key!('i') => self.set_mode(EditorMode::Insert),
-      _ => ()
+  fn read_event(&mut self) -> Event {
+    if let Some(event) = self.pending_events.pop_front() {
+      event
+    } else {
+      self.read_terminal()
+    }
+  }
+
+  fn read_terminal(&mut self) -> Event {
+    let event = read().unwrap();
+    if let Some((_, recorded)) = &mut self.recording {
+      if let Some(key) = keys::encode(&event) {
+        recorded.push_str(&key)
+      }
     }
+    event
   }
 
   fn evaluate_expr(&mut self) -> Result<(), ()> {
     let mut next_mode_not_normal = false;
+    let command = self.status_bar.cmd.clone();
     if self.status_bar.cmd.starts_with(":set") {
       let copied_cmd = self.status_bar.cmd
       .clone();
@@ -435,6 +1085,7 @@ impl Editor {
           "line" => {
             if let Some(line_no) = split_command.get(idx + 1) {
               if let Ok(line_no) = line_no.parse::<usize>() {
+                self.record_jump();
                 self.goto_line(line_no);
               }
             }
@@ -442,23 +1093,79 @@ impl Editor {
           }
           "filename" => {
             if let Some(file_name) = split_command.get(idx + 1) {
-              self.file.set_name(*file_name)
+              self.file.set_name(file_name)
+            }
+            break
+          },
+          "ignorecase" | "ic" => self.search_options.ignore_case = true,
+          "noignorecase" | "noic" => self.search_options.ignore_case = false,
+          "smartcase" | "scs" => self.search_options.smart_case = true,
+          "nosmartcase" | "noscs" => self.search_options.smart_case = false,
+          "literal" => self.search_options.literal = true,
+          "noliteral" => self.search_options.literal = false,
+          "autocomplete" | "ac" => self.autocomplete = true,
+          "noautocomplete" | "noac" => self.autocomplete = false,
+          "autopair" | "ap" => self.autopair = true,
+          "noautopair" | "noap" => self.autopair = false,
+          "clipboard" => {
+            if let Some(provider) = split_command.get(idx + 1)
+              .and_then(|name| ClipboardProvider::from_name(name)) {
+              self.clipboard.provider = provider
             }
             break
           },
+          // the rest of the line is the command, none goes back to the guess
+          "makeprg" => {
+            let program = split_command[(idx + 1)..].join(" ");
+            self.make_program = Some(program).filter(|program| !program.is_empty());
+            break
+          },
           _ => ()
         }
       }
+    } else if let Some(mut pattern) = search::parse(self.status_bar.cmd.strip_prefix(':').unwrap_or(&self.status_bar.cmd)) {
+      // the search starts over from where the pattern started being typed
+      if let Some((row, column)) = self.search_origin.take() {
+        self.jump_to(row, column)
+      }
+      if pattern.pattern.is_empty() {
+        match &self.last_search {
+          Some(last) => pattern.pattern = last.pattern.clone(),
+          None => self.status_bar.message = String::from("no previous pattern")
+        }
+      }
+      if !pattern.pattern.is_empty() {
+        match search::compile(&pattern.pattern, &self.search_options) {
+          Ok(_) => {
+            self.last_search = Some(pattern);
+            self.search_next(false, 1)
+          },
+          Err(why) => self.status_bar.message = why
+        }
+      }
+    } else if command.strip_prefix(':').is_some_and(|command| self.run_named_command(command)) {
+      // already carried out while being recognised
+    } else if let Some(parsed) = self.status_bar.cmd.strip_prefix(':').and_then(|cmd| substitute::parse(cmd, &self.search_options)) {
+      self.status_bar.cmd.clear();
+      self.status_bar.cmd_chars = 0;
+      self.set_mode(EditorMode::Normal);
+      match parsed {
+        Ok(substitute) => self.substitute(substitute),
+        Err(why) => self.status_bar.message = why
+      }
     } else {
       let mut commands = self.status_bar.cmd.chars().rev().collect::<String>();
+      let mut register = None;
       while let Some(cmd) = commands.pop() {
         match cmd {
-          'q' => if self.file.name() != "" || !self.altered { self._quit = true },
+          '"' => {
+            register = commands.pop().filter(|name| Registers::is_valid(*name));
+          },
+          'q' if !self.file.name().is_empty() || !self.altered => self._quit = true,
           'e' => self.move_to_line_end(),
           'a' => self.move_to_line_beginning(),
           'A' => {
-            self.move_to_line_end();
-            self.set_mode(EditorMode::Insert);
+            self.execute_normal(NormalCommand::new(None, Action::Insert(InsertAt::LineEnd)));
             next_mode_not_normal = true;
           },
           'w' => {
@@ -472,56 +1179,48 @@ impl Editor {
             }
           },
           'i' => {
-            self.set_mode(EditorMode::Insert);
+            self.execute_normal(NormalCommand::new(None, Action::Insert(InsertAt::Cursor)));
             next_mode_not_normal = true
           },
-          'd' => {
-            let num_lines = numeric_modifer(&mut commands);
-            for _ in 0..num_lines {
-              let row_no = self.position.0 as usize + self.view_frame.0;
-              self.history.push(HistoryNode::create(&self.file.rows[row_no..(row_no + 1)], row_no..(row_no + 1)));
-              self.file.clear_row(row_no);
-              self.move_to_line_beginning();
-              self.scroll(Direction::Down)
-            }
-            self.move_to_line_beginning()
+          'd' | 'D' | 'y' | 'p' | 'P' => {
+            let count = Some(numeric_modifer(&mut commands) as usize);
+            let action = match cmd {
+              'd' => Action::Operate(Operator::Delete, Target::Lines),
+              'D' => Action::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+              'y' => Action::Operate(Operator::Yank, Target::Lines),
+              'p' => Action::Put(true),
+              _ => Action::Put(false)
+            };
+            self.execute_normal(NormalCommand {
+              register: register.take(),
+              count: if cmd == 'D' { None } else { count },
+              action
+            })
+          },
+          'g' => {
+            self.record_jump();
+            self.move_to_beginning()
+          },
+          'G' => {
+            self.record_jump();
+            self.move_to_end()
           },
-          'g' => self.move_to_beginning(),
-          'G' => self.move_to_end(),
           'x' => {
             self._quit = true;
             self.altered = false;
+            self.modified.clear()
           },
           'u' => {
-            let reps = numeric_modifer(&mut commands);
-            for _ in 0..reps {
-              self.undo()
-            }
+            let reps = numeric_modifer(&mut commands) as usize;
+            self.execute_normal(NormalCommand::new(Some(reps), Action::Undo))
           },
-          '/' => {
-            if let Some(arg) = word_modifier(&mut commands) {
-              next_mode_not_normal = true;
-              self.search(arg);
-              if let Some(result_iter) = &mut self.search_results {
-                if let Some((row_no, row_offset)) = result_iter.next() {
-                  self.goto_line(row_no + 1);
-                  self.move_to(row_offset as u16 + self.buffer + 1, self.position.0)
-                } else {
-                  self.set_mode(EditorMode::Normal);
-                  next_mode_not_normal = false
-                }
-              } else {
-                next_mode_not_normal = false
-              }
-            }
-            break
-          }
           _ => ()
         }
       }
     }
     self.status_bar.cmd.clear();
     self.status_bar.cmd_chars = 0;
+    self.search_origin = None;
 
     if next_mode_not_normal {
       Err(())
@@ -531,13 +1230,17 @@ impl Editor {
   }
 
   fn set_mode(&mut self, mode: EditorMode) {
+    if mode != EditorMode::Insert {
+      self.popup = None;
+      self.snippet = None
+    }
     self.status_bar.set_mode(&mode);
     self.set_cursor(&mode);
     self.mode = mode;
   }
 
   fn goto_line(&mut self, line_no: usize) {
-    if line_no as usize <= self.file.len() {
+    if line_no <= self.file.len() {
       let difference = line_no as i64 - self.position.0 as i64 - self.view_frame.0 as i64;
       if difference <= 0 {
         for _ in 0..(difference.abs() + 1) {
@@ -561,7 +1264,7 @@ impl Editor {
         let file = &self.file;
         // ensure that we are within the bounds of the file,
         // add one null line to allow buffer to grow
-        if self.view_frame.1 - 2 < file.rows.len() as usize
+        if self.view_frame.1 - 2 < file.rows.len()
         || self.position.0 + 2 != self.terminal.height {
           // grab row below the current cursor row
           if let Some(row) = file.get_row(self.position.0 as usize + self.view_frame.0 + 1) {
@@ -637,7 +1340,7 @@ impl Editor {
     let _ = execute!(
       stdout(),
       SetCursorShape(match *mode {
-        EditorMode::Insert => CursorShape::Line,
+        EditorMode::Insert | EditorMode::Picker => CursorShape::Line,
         _ => CursorShape::Block
       })
     );
@@ -654,7 +1357,7 @@ impl Editor {
     let mut slice = row.content()[(self.position.1 - self.buffer - 1) as usize..].chars().enumerate();
     while let Some((_, character)) = slice.next() {
       if character.is_whitespace() || character.is_ascii_punctuation() {
-        while let Some((index, character)) = slice.next() {
+        for (index, character) in slice.by_ref() {
           if !(character.is_whitespace() || character.is_ascii_punctuation()) {
             self.move_to(self.position.1 + index as u16, self.position.0);
             return
@@ -670,7 +1373,7 @@ impl Editor {
     let mut slice = row.content()[0..(self.position.1 - self.buffer) as usize].chars().rev().enumerate();
     while let Some((_, character)) = slice.next() {
       if character.is_whitespace() || character.is_ascii_punctuation() {
-        while let Some((index, character)) = slice.next() {
+        for (index, character) in slice.by_ref() {
           if !(character.is_whitespace() || character.is_ascii_punctuation()) {
             self.move_to(self.position.1 - index as u16, self.position.0);
             return
@@ -753,48 +1456,81 @@ impl Editor {
   }
 
   fn insert_row(&mut self) {
-    let line = self.view_frame.0 + self.position.0 as usize;
-    let column = self.position.1 - self.buffer - 1;
+    let (line, column) = self.cursor();
+    // Enter between the halves of a bracket pair puts the closing one on a
+    // line of its own, as after a '{' ending the line that nothing closes yet
+    let between_pair = column > 0 && {
+      let graphemes = motion::graphemes(&self.file, line);
+      let (previous, next) = (graphemes[column - 1], graphemes.get(column).copied());
+      self.pairs()
+        .iter()
+        .any(|(open, close)| open != close && previous == open.to_string() && next == Some(close.to_string().as_str()))
+    };
+    let closing_brace = self.autopair && !between_pair && {
+      let graphemes = motion::graphemes(&self.file, line);
+      let last = graphemes.iter().rposition(|grapheme| !grapheme.trim().is_empty());
+      let closed = self.file.rows.get(line + 1).is_some_and(|next| {
+        next.content().trim_start().starts_with('}') && indentation(next) == indentation(&self.file.rows[line])
+      });
+      last.is_some_and(|last| graphemes[last] == "{" && last < column && !closed && !self.in_literal((line, last)))
+    };
     let file = self.get_file_mut();
     let row = file.get_row_mut(line).unwrap();
-    let offset = {
-      let mut counter: usize = 0;
-      for character in row.content().chars() {
-        if character.is_whitespace() {
-          counter += 1
-        } else {
-          break
-        }
-      }
-      counter / 2 * 2
-    };
-    let mut add_closing_brace = false;
-    if let Some(last_key) = row.content().trim_end().chars().last() {
-      add_closing_brace = last_key == '{';
-    }
     let new_row = row.add_new_line(if column == 0 {
       NLPositionDescriptor::Beginning
-    } else if column as usize == row.len() - 1 {
+    } else if column == row.len() {
       NLPositionDescriptor::End
     } else {
-      NLPositionDescriptor::Middle((column - 1) as usize)
+      NLPositionDescriptor::Middle(column - 1)
     });
     file.insert_row(line + 1, new_row);
-    self.scroll(Direction::Down);
-    if add_closing_brace {
-      self.insert_row();
-      for _ in 0..offset {
-        self.insert(' ')
-      }
-      self.insert('}');
-      self.scroll(Direction::Up);
-      for _ in 0..(offset + 2) {
-        self.insert(' ')
-      }
-    } else {
-      self.move_to_line_start()
+    // what follows the cursor goes down a line, indented as the rules say
+    let rest = self.file.rows[line + 1].content().trim_start().to_string();
+    if closing_brace || between_pair {
+      let closing = if closing_brace { String::from("}") } else { rest.clone() };
+      self.file.insert_row(line + 2, Row::from(""));
+      let indent = self.rule_indent(line + 2, closing.trim());
+      self.set_row_content(line + 2, &format!("{}{}", " ".repeat(indent), closing));
+      self.set_row_content(line + 1, "")
     }
+    let rest = if closing_brace || between_pair { String::new() } else { rest };
+    let indent = self.rule_indent(line + 1, rest.trim());
+    self.set_row_content(line + 1, &format!("{}{}", " ".repeat(indent), rest));
     self.set_buffer();
+    self.jump_to(line + 1, indent)
+  }
+
+  // replaces the text of 'row', as typing does without a history of its own
+  fn set_row_content(&mut self, row: usize, content: &str) {
+    let row = self.file.get_row_mut(row).unwrap();
+    let len = row.len();
+    row.remove(0..len);
+    row.insert_str(0, content)
+  }
+
+  // the indentation the language's rules give a line at 'row' whose code is
+  // 'code', the rows above it as they are
+  fn rule_indent(&self, row: usize, code: &str) -> usize {
+    let language = self.file.language();
+    let start = self.file.item_start(row);
+    let lines = indent::lines(language, &self.file.rows[start..row]);
+    indent::indent(language, &indent::above(&lines), code)
+  }
+
+  // indents 'row' as the rules say, the cursor staying on its character
+  fn reindent_row(&mut self, row: usize) {
+    let code = indent::lines(self.file.language(), &self.file.rows[row..(row + 1)]).remove(0).1;
+    let new = self.rule_indent(row, &code);
+    let old = indentation(&self.file.rows[row]);
+    if new == old {
+      return
+    }
+    let content = self.file.rows[row].content().trim_start().to_string();
+    self.set_row_content(row, &format!("{}{}", " ".repeat(new), content));
+    let (cursor_row, column) = self.cursor();
+    if cursor_row == row {
+      self.jump_to(row, (column + new).saturating_sub(old))
+    }
   }
 
   fn move_to_line_start(&mut self) {
@@ -803,81 +1539,2160 @@ impl Editor {
 
   fn undo(&mut self) {
     if let Some(node) = self.history.pop() {
-      let (range, mut altered_rows) = node.extract();
-      altered_rows.reverse();
-      let rest_cursor = range.end;
-      for row_no in range {
-        self.file.replace(row_no, altered_rows.pop().unwrap())
-      }
-      self.goto_line(rest_cursor);
+      let (range, altered_rows) = node.extract();
+      let rest_cursor = std::cmp::max(range.start + altered_rows.len(), 1);
+      self.file.splice_rows(range, altered_rows);
+      self.altered = true;
+      self.set_buffer();
+      self.goto_line(std::cmp::min(rest_cursor, self.file.len()));
       self.move_to_line_end()
     }
   }
 
-  fn search(&mut self, expr: String) {
-    let (num_results, results) = self.file.search_for(&expr);
-    if num_results > 0 {
-      self._search_total = num_results;
-      self._search_current = 1;
-      self.set_mode(EditorMode::Search);
-      self.search_results = Some(results.into_iter())
-    }
+  // (row, column) of the cursor within the document
+  fn cursor(&self) -> (usize, usize) {
+    (
+      self.view_frame.0 + self.position.0 as usize,
+      self.position.1.saturating_sub(self.buffer + 1) as usize
+    )
   }
 
-  fn render(&mut self) {
-    let _ = execute!(
-      stdout(),
-      Hide,
-      SavePosition,
-      MoveTo(0, 0),
-    );
-    let num_rows = self.file.rows.len();
-    let buffer = num_rows.to_string().chars().count();
-    self.buffer = buffer as u16;
-    if self.mode != EditorMode::Command {
-      self.file.highlight();
-    }
-    for terminal_row_no in self.view_frame.0..(self.view_frame.1 - 1) {
-      if terminal_row_no < num_rows {
-        self.clear_row();
-        let used = buffer - (terminal_row_no + 1).to_string().chars().count();
-        self.write_row(terminal_row_no + 1, used, self.file.rows.get(terminal_row_no).unwrap());
+  // moves the cursor to a (row, column) position within the document
+  fn jump_to(&mut self, row: usize, column: usize) {
+    let row = std::cmp::min(row, self.file.len() - 1);
+    self.goto_line(row + 1);
+    let row_len = self.file.get_row(row).unwrap().len();
+    self.move_to(std::cmp::min(column, row_len) as u16 + self.buffer + 1, self.position.0)
+  }
+
+  // runs an edit touching 'rows' and records it as a single undo step
+  fn edit_rows<F: FnOnce(&mut Document)>(&mut self, rows: Range<usize>, edit: F) {
+    let before_len = self.file.len();
+    let snapshot = HistoryNode::create(&self.file.rows[rows.clone()], rows.clone());
+    edit(&mut self.file);
+    let (old_range, old_rows) = snapshot.extract();
+    let end = old_range.end + self.file.len() - before_len;
+    self.history.push(HistoryNode::create(&old_rows, old_range.start..end));
+    self.altered = true;
+    self.set_buffer()
+  }
+
+  fn execute_normal(&mut self, command: NormalCommand) {
+    let register = command.register.filter(|name| Registers::is_valid(*name));
+    let count = command.count.unwrap_or(1);
+    if command.action.is_change() {
+      if self.refuse_edit() {
+        return
+      }
+      let change = Change::new(command);
+      self.pending_change = None;
+      self.perform_normal(command, register, count);
+      if self.mode == EditorMode::Insert {
+        self.pending_change = Some(change)
       } else {
-        self.write_empty_line();
+        self.last_change = Some(change)
       }
+      return
     }
-    match self.mode {
-      EditorMode::Search => self.status_bar.render(self._search_current, self._search_total),
-      _ => self.status_bar.render(self.view_frame.0 + self.position.0 as usize + 1, self.file.len())
+    self.perform_normal(command, register, count)
+  }
+
+  // replays the last change at the cursor, a count replaces the original one
+  fn repeat_change(&mut self, count: Option<usize>) {
+    let mut change = match self.last_change.clone() {
+      Some(change) => change,
+      None => return
+    };
+    if self.refuse_edit() {
+      return
     }
-    match self.mode {
-      EditorMode::Command => (),
-      _ => {
-        let _ = execute!(stdout(), RestorePosition, Show);
-        let (column, row) = position().unwrap();
-        if column == 0 {
-          self.position.1 = self.buffer + 1;
-          let _ = execute!(
-            stdout(),
-            MoveTo(self.position.1, row)
-          );
-        }
+    if count.is_some() {
+      change.command.count = count;
+      change = Change { inserted: change.inserted, ..Change::new(change.command) }
+    }
+    let register = change.command.register.filter(|name| Registers::is_valid(*name));
+    self.perform_normal(change.command, register, change.command.count.unwrap_or(1));
+    if self.mode == EditorMode::Insert {
+      for time in 0..change.count {
+        self.retype(&change, time > 0)
       }
+      self.set_mode(EditorMode::Normal)
     }
-    let _ = stdout().flush();
+    self.last_change = Some(change)
   }
-}
 
-impl Drop for Editor {
-  fn drop(&mut self) {
-    let _ = self.terminal.clear();
-    if self.altered && self._quit {
+  // types what an insert typed once more, o and O first opening a line below when 'new_line'
+  fn retype(&mut self, change: &Change, new_line: bool) {
+    if new_line && matches!(change.command.action, Action::Insert(InsertAt::LineBelow | InsertAt::LineAbove)) {
+      self.start_insert(InsertAt::LineBelow)
+    }
+    for key in change.inserted.iter().cloned() {
+      self.insert_key(key)
+    }
+  }
+
+  fn perform_normal(&mut self, command: NormalCommand, register: Option<char>, count: usize) {
+    match command.action {
+      Action::Move(Motion::Up) => {
+        for _ in 0..count {
+          self.scroll(Direction::Up)
+        }
+      },
+      Action::Move(Motion::Down) => {
+        for _ in 0..count {
+          self.scroll(Direction::Down)
+        }
+      },
+      Action::Move(Motion::Mark(name)) | Action::Move(Motion::MarkExact(name)) => {
+        if let Some((file, pos)) = self.marks.get(&self.file.file_name, name) {
+          self.record_jump();
+          if self.goto_location((file, pos)) {
+            if let Action::Move(Motion::Mark(_)) = command.action {
+              let row = self.cursor().0;
+              self.jump_to(row, motion::first_non_blank(&self.file, row))
+            }
+          }
+        }
+      },
+      Action::Move(motion) => {
+        if let Some((row, column)) = motion::target(&self.file, self.cursor(), motion, command.count) {
+          if motion.is_jump() {
+            self.record_jump()
+          }
+          self.jump_to(row, column)
+        }
+      },
+      Action::Operate(op, target) => {
+        let pos = self.cursor();
+        let on_blank = motion::graphemes(&self.file, pos.0).get(pos.1).is_none_or(|g| g.trim().is_empty());
+        let span = match target {
+          Target::Lines => Some(Span::Lines(pos.0..std::cmp::min(pos.0 + count, self.file.len()))),
+          // cw on a word only changes up to the word's end, like ce
+          Target::Motion(Motion::WordForward) if op == Operator::Change && count == 1 && !on_blank => {
+            match textobject::select(&self.file, pos, TextObject::Word, true, 1) {
+              Some(Span::Chars(_, end)) => Some(Span::Chars(pos, end)),
+              _ => None
+            }
+          },
+          Target::Motion(motion @ Motion::Mark(name)) | Target::Motion(motion @ Motion::MarkExact(name)) => {
+            self.mark_position(name, motion == Motion::MarkExact(name))
+              .map(|target| motion::span_to(&self.file, pos, target, motion))
+          },
+          Target::Motion(motion) => motion::span(&self.file, pos, motion, command.count),
+          Target::Object(object, inner) => textobject::select(&self.file, pos, object, inner, count)
+        };
+        if let Some(span) = span {
+          self.apply_operator(op, span, register)
+        }
+      },
+      Action::Insert(at) => self.start_insert(at),
+      Action::Put(after) => self.put(register, count, after),
+      Action::Undo => {
+        for _ in 0..count {
+          self.undo()
+        }
+      },
+      Action::Repeat => self.repeat_change(command.count),
+      Action::Record(name) => {
+        if name.is_ascii_alphabetic() {
+          self.recording = Some((name, String::new()));
+          self.status_bar.recording = Some(name)
+        }
+      },
+      Action::Replay(name) => self.replay_macro(name, count),
+      Action::SetMark(name) => self.marks.set(&self.file.file_name, name, self.cursor()),
+      Action::JumpOlder => {
+        if let Some(location) = self.marks.jump_older(&self.file.file_name, self.cursor(), count) {
+          self.goto_location(location);
+        }
+      },
+      Action::JumpNewer => {
+        if let Some(location) = self.marks.jump_newer(count) {
+          self.goto_location(location);
+        }
+      },
+      Action::ChangeOlder | Action::ChangeNewer => {
+        let pos = if command.action == Action::ChangeOlder {
+          self.marks.change_older(&self.file.file_name, count)
+        } else {
+          self.marks.change_newer(&self.file.file_name, count)
+        };
+        match pos {
+          Some((row, column)) => self.jump_to(row, column),
+          None => self.status_bar.message = String::from("no further changes")
+        }
+      },
+      Action::SearchNext(reverse) => self.search_next(reverse, count),
+      Action::SearchWord(backward) => {
+        if let Some(word) = self.word_under_cursor() {
+          self.last_search = Some(SearchPattern {
+            pattern: format!(r"\m\b{}\b", regex::escape(&word)),
+            backward,
+            offset: None
+          });
+          self.search_next(false, count)
+        }
+      },
+      Action::CommandLine(prompt) => {
+        self.set_mode(EditorMode::Command);
+        self.status_bar.add_command(prompt);
+      },
+      Action::Hover => self.lsp_request(|client, pos| client.hover(pos)),
+      Action::GotoDefinition => self.lsp_request(|client, pos| client.definition(pos)),
+      Action::References => self.lsp_request(|client, pos| client.references(pos)),
+      Action::NextDiagnostic(backward) => self.next_diagnostic(backward, count)
+    }
+  }
+
+  // the rows a command range covers, None if it lies outside the document
+  fn resolve_range(&self, range: LineRange) -> Option<Range<usize>> {
+    let last = self.file.len() - 1;
+    let resolve = |(address, offset): (Address, i64)| -> Option<usize> {
+      let row = match address {
+        Address::Line(line) => line.saturating_sub(1),
+        Address::Current => self.cursor().0,
+        Address::Last => last,
+        Address::Mark(name) => self.mark_position(name, false)?.0
+      } as i64 + offset;
+      if row < 0 || row > last as i64 {
+        None
+      } else {
+        Some(row as usize)
+      }
+    };
+    match range {
+      LineRange::Whole => Some(0..(last + 1)),
+      LineRange::Lines(first, second) => {
+        let (first, second) = (resolve(first)?, resolve(second)?);
+        Some(std::cmp::min(first, second)..(std::cmp::max(first, second) + 1))
+      }
+    }
+  }
+
+  // asks whether to replace the match at 'row', 'bytes'; None means stop
+  fn confirm_match(&mut self, row: usize, bytes: Range<usize>, replacement: &str) -> Option<char> {
+    let content = self.file.rows[row].content();
+    let column = Row::from(&content[..bytes.start]).len();
+    let end = Row::from(&content[..bytes.end]).len();
+    self.marked = vec![(row, column..end)];
+    self.jump_to(row, column);
+    self.status_bar.message = format!("replace with {} (y/n/a/q/l)?", replacement);
+    self.render();
+    let answer = loop {
+      match self.read_event() {
+        char_key!(key @ ('y' | 'n' | 'a' | 'q' | 'l')) => break Some(key),
+        special_key!(KeyCode::Esc) => break None,
+        _ => ()
+      }
+    };
+    self.marked.clear();
+    self.status_bar.message.clear();
+    answer.filter(|key| *key != 'q')
+  }
+
+  // runs :s over its range as a single undo step
+  fn substitute(&mut self, substitute: Substitute) {
+    if !substitute.count_only && self.refuse_edit() {
+      return
+    }
+    let mut rows = match self.resolve_range(substitute.range) {
+      Some(rows) => rows,
+      None => {
+        self.status_bar.message = String::from("invalid range");
+        return
+      }
+    };
+    if let Some(count) = substitute.count {
+      rows = (rows.end - 1)..std::cmp::min(rows.end - 1 + count, self.file.len())
+    }
+    if substitute.count_only {
+      let (matches, lines) = self.file.rows[rows]
+        .iter()
+        .map(|row| {
+          let found = substitute.regex.find_iter(row.content()).count();
+          if substitute.global { found } else { std::cmp::min(found, 1) }
+        })
+        .filter(|found| *found > 0)
+        .fold((0, 0), |(matches, lines), found| (matches + found, lines + 1));
+      self.status_bar.message = format!("{} matches on {} lines", matches, lines);
+      return
+    }
+    let mut replaced = 0;
+    let mut changed_lines = 0;
+    let mut last_changed = None;
+    self.as_single_edit(|editor| {
+      let mut confirm = substitute.confirm;
+      let mut row = rows.start;
+      let mut end = rows.end;
+      let mut stop = false;
+      while row < end && !stop {
+        let content = editor.file.rows[row].content().to_string();
+        let captures: Vec<_> = if substitute.global {
+          substitute.regex.captures_iter(&content).collect()
+        } else {
+          substitute.regex.captures(&content).into_iter().collect()
+        };
+        // the row is rebuilt match by match so confirm mode shows the progress
+        let mut result = String::new();
+        let mut consumed = 0;
+        let mut occupied = 1;
+        let mut line_replaced = false;
+        for caps in captures {
+          let found = caps.get(0).unwrap();
+          let replacement = substitute.expand(&caps);
+          let replace = if confirm {
+            let shown_row = row + result.matches('\n').count();
+            let shown_start = result.rsplit('\n').next().unwrap_or("").len() + found.start() - consumed;
+            match editor.confirm_match(shown_row, shown_start..(shown_start + found.range().len()), &replacement) {
+              Some('a') => {
+                confirm = false;
+                true
+              },
+              Some('l') => {
+                stop = true;
+                true
+              },
+              Some(answer) => answer == 'y',
+              None => {
+                stop = true;
+                false
+              }
+            }
+          } else {
+            true
+          };
+          result.push_str(&content[consumed..found.start()]);
+          result.push_str(if replace { &replacement } else { found.as_str() });
+          consumed = found.end();
+          if replace {
+            replaced += 1;
+            line_replaced = true;
+            let lines: Vec<Row> = format!("{}{}", result, &content[consumed..])
+              .split('\n')
+              .map(Row::from)
+              .collect();
+            let new_len = lines.len();
+            editor.file.splice_rows(row..(row + occupied), lines);
+            end = end + new_len - occupied;
+            occupied = new_len
+          }
+          if stop {
+            break
+          }
+        }
+        if line_replaced {
+          changed_lines += 1;
+          last_changed = Some(row + occupied - 1)
+        }
+        row += occupied
+      }
+    });
+    match last_changed {
+      Some(row) => {
+        self.altered = true;
+        self.set_buffer();
+        self.jump_to(row, motion::first_non_blank(&self.file, row));
+        if replaced > 1 {
+          self.status_bar.message = format!("{} substitutions on {} lines", replaced, changed_lines)
+        }
+      },
+      None => self.status_bar.message = format!("pattern not found: {}", substitute.regex.as_str())
+    }
+  }
+
+  fn record_jump(&mut self) {
+    // generated buffers cannot be opened again by name
+    if !self.file.scratch {
+      self.marks.push_jump(&self.file.file_name, self.cursor())
+    }
+  }
+
+  // a mark's position within the current file, clamped to the document
+  fn mark_position(&self, name: char, exact: bool) -> Option<(usize, usize)> {
+    let (file, (row, column)) = self.marks.get(&self.file.file_name, name)?;
+    if file != self.file.file_name {
+      return None
+    }
+    let row = std::cmp::min(row, self.file.len() - 1);
+    if exact {
+      Some((row, std::cmp::min(column, self.file.get_row(row)?.len())))
+    } else {
+      Some((row, motion::first_non_blank(&self.file, row)))
+    }
+  }
+
+  // moves to a position that may be in another file, opening that file first
+  fn goto_location(&mut self, (file, (row, column)): Location) -> bool {
+    if file != self.file.file_name && !self.open_file(&file) {
+      return false
+    }
+    self.jump_to(row, column);
+    true
+  }
+
+  // replaces the open document with a file
+  fn open_file(&mut self, file_name: &str) -> bool {
+    if Path::new(file_name).is_dir() {
+      return self.explore(file_name, None)
+    }
+    if !self.file.scratch && file_name == self.file.file_name {
+      return true
+    }
+    if !self.leave_document() {
+      return false
+    }
+    match self.modified.remove(file_name) {
+      Some(document) => {
+        self.show_document(document);
+        self.altered = true
+      },
+      None => self.show_document(Document::open(file_name).unwrap_or_else(|_| Document::new(file_name)))
+    }
+    true
+  }
+
+  // the current document is kept with its changes while another replaces it,
+  // which an unnamed one can't be as it could never be opened again
+  fn leave_document(&mut self) -> bool {
+    if self.altered && !self.file.scratch && self.file.name().is_empty() {
+      self.status_bar.message = String::from("no write since last change");
+      return false
+    }
+    true
+  }
+
+  // the open document and every one kept with changes is written out
+  fn write_all(&mut self) -> bool {
+    if self.altered && !self.file.scratch {
+      if self.file.name().is_empty() {
+        self.status_bar.message = String::from("no file name to write to");
+        return false
+      }
+      if let Err(why) = self.file.save() {
+        self.status_bar.message = why.to_string();
+        return false
+      }
+      if let Some(client) = self.lsp_client() {
+        let _ = client.saved();
+      }
+      self.altered = false
+    }
+    let names: Vec<String> = self.modified.keys().cloned().collect();
+    for name in names {
+      if let Err(why) = self.modified[&name].save() {
+        self.status_bar.message = format!("{}: {}", name, why);
+        return false
+      }
+      self.modified.remove(&name);
+    }
+    true
+  }
+
+  fn show_document(&mut self, document: Document) {
+    // every file keeps its undo history while others are open
+    let history = if document.scratch {
+      History::new()
+    } else {
+      self.histories.remove(document.name()).unwrap_or_else(History::new)
+    };
+    let previous = std::mem::replace(&mut self.file, document);
+    let previous_history = std::mem::replace(&mut self.history, history);
+    if !previous.scratch && !previous.name().is_empty() {
+      self.histories.insert(previous.file_name.clone(), previous_history);
+      if self.altered {
+        self.modified.insert(previous.file_name.clone(), previous);
+      }
+    }
+    if !self.file.scratch && !self.file.name().is_empty() {
+      let name = self.file.file_name.clone();
+      self.recent_files.retain(|recent| *recent != name);
+      recent::remember(&mut self.old_files, &name);
+      self.recent_files.insert(0, name);
+      self.recent_files.truncate(MAX_RECENT_FILES)
+    }
+    self.showing_quickfix = false;
+    self.explorer = None;
+    self.altered = false;
+    self.search_matches.clear();
+    self.matches_of = None;
+    self.view_frame = (0, self.terminal.size().1 as usize);
+    self.position = (0, 0);
+    self.set_buffer();
+    self.move_to_line_start();
+    self.attach_lsp();
+    self.show_build_diagnostics()
+  }
+
+  // replaces the open document with a listing of 'dir', the cursor on 'focus'
+  fn explore(&mut self, dir: &str, focus: Option<&str>) -> bool {
+    let explorer = match Explorer::read(dir) {
+      Ok(explorer) => explorer,
+      Err(why) => {
+        self.status_bar.message = format!("{}: {}", dir, why);
+        return false
+      }
+    };
+    if !self.leave_document() {
+      return false
+    }
+    self.show_document(Document::scratch(&explorer.dir, &explorer.lines()));
+    let row = focus.and_then(|name| explorer.row_of(name)).unwrap_or(0);
+    self.explorer = Some(explorer);
+    self.jump_to(row, 2);
+    true
+  }
+
+  // :Explore [dir], without a directory the one holding the open file
+  fn explore_command(&mut self, dir: &str) {
+    if !dir.is_empty() {
+      self.explore(dir, None);
+      return
+    }
+    if let Some(explorer) = &self.explorer {
+      let (dir, focus) = (explorer.dir.clone(), explorer.entry(self.cursor().0).map(|entry| entry.name.clone()));
+      self.explore(&dir, focus.as_deref());
+      return
+    }
+    let path = Path::new(&self.file.file_name);
+    let dir = match path.parent() {
+      Some(parent) if !self.file.scratch && !parent.as_os_str().is_empty() => parent.to_string_lossy().to_string(),
+      _ => String::from(".")
+    };
+    let focus = path.file_name().map(|name| name.to_string_lossy().to_string());
+    self.explore(&dir, focus.as_deref());
+  }
+
+  // the explorer's rows stand for its entries by position, so the listing
+  // can't be edited or its commands would act on the wrong entry
+  fn refuse_edit(&mut self) -> bool {
+    if self.explorer.is_some() {
+      self.status_bar.message = String::from("the directory listing can't be edited");
+    }
+    self.explorer.is_some()
+  }
+
+  fn explore_parent(&mut self, explorer: &Explorer) {
+    // the directory being left is where the cursor lands in its parent
+    let name = Path::new(&explorer.dir)
+      .canonicalize()
+      .ok()
+      .and_then(|path| path.file_name().map(|name| name.to_string_lossy().to_string()));
+    self.explore(&explorer.parent(), name.as_deref());
+  }
+
+  fn open_explorer_entry(&mut self) {
+    let explorer = match &self.explorer {
+      Some(explorer) => explorer.clone(),
+      None => return
+    };
+    match explorer.entry(self.cursor().0) {
+      None => self.explore_parent(&explorer),
+      Some(entry) if entry.is_dir => {
+        self.explore(&explorer.path(&entry.name), None);
+      },
+      Some(entry) => {
+        self.open_file(&explorer.path(&entry.name));
+      }
+    }
+  }
+
+  // the listing's own keys: - goes up, % creates a file, d a directory,
+  // R renames and D deletes the entry under the cursor
+  fn explorer_command(&mut self, key: char) {
+    let explorer = match &self.explorer {
+      Some(explorer) => explorer.clone(),
+      None => return
+    };
+    let row = self.cursor().0;
+    let entry = explorer.entry(row).cloned();
+    let done = match (key, entry) {
+      ('-', _) => return self.explore_parent(&explorer),
+      ('%', _) => self.prompt("new file: ", "").map(|name| {
+        let created = std::fs::OpenOptions::new().write(true).create_new(true).open(explorer.path(&name));
+        (created.map(|_| ()), Some(name))
+      }),
+      ('d', _) => self.prompt("new directory: ", "").map(|name| {
+        (std::fs::create_dir_all(explorer.path(&name)), Some(name))
+      }),
+      ('R', Some(entry)) => self.prompt("rename to: ", &entry.name).map(|name| {
+        let (from, to) = (explorer.path(&entry.name), explorer.path(&name));
+        let renamed = std::fs::rename(&from, &to);
+        if renamed.is_ok() {
+          self.move_kept(&from, &to)
+        }
+        (renamed, Some(name))
+      }),
+      ('D', Some(entry)) => {
+        let kind = if entry.is_dir { "directory" } else { "file" };
+        self.status_bar.message = format!("delete {} {}? (y/n)", kind, entry.name);
+        self.render();
+        let accepted = loop {
+          match self.read_event() {
+            char_key!('y') => break true,
+            char_key!('n') | char_key!('q') | special_key!(KeyCode::Esc) => break false,
+            _ => ()
+          }
+        };
+        self.status_bar.message.clear();
+        accepted.then(|| {
+          let path = explorer.path(&entry.name);
+          self.histories.remove(&path);
+          self.modified.remove(&path);
+          // directories have to be emptied first, nothing is removed recursively
+          let removed = if entry.is_dir { std::fs::remove_dir(path) } else { std::fs::remove_file(path) };
+          (removed, None)
+        })
+      },
+      _ => None
+    };
+    let (result, focus) = match done {
+      Some(done) => done,
+      None => return
+    };
+    let failed = result.err();
+    if self.explore(&explorer.dir, focus.as_deref()) && focus.is_none() {
+      self.jump_to(std::cmp::min(row, self.file.len() - 1), 2)
+    }
+    if let Some(why) = failed {
+      self.status_bar.message = why.to_string()
+    }
+  }
+
+  // moves the undo histories and unsaved documents of a renamed file, or of
+  // everything under a renamed directory, to their new names
+  fn move_kept(&mut self, from: &str, to: &str) {
+    let moved = |name: &str| match Path::new(name).strip_prefix(from) {
+      Ok(rest) if rest.as_os_str().is_empty() => Some(to.to_string()),
+      Ok(rest) => Some(Path::new(to).join(rest).to_string_lossy().into_owned()),
+      Err(_) => None
+    };
+    let names: Vec<String> = self.histories.keys().filter(|name| moved(name).is_some()).cloned().collect();
+    for name in names {
+      if let (Some(history), Some(new_name)) = (self.histories.remove(&name), moved(&name)) {
+        self.histories.insert(new_name, history);
+      }
+    }
+    let names: Vec<String> = self.modified.keys().filter(|name| moved(name).is_some()).cloned().collect();
+    for name in names {
+      if let (Some(mut document), Some(new_name)) = (self.modified.remove(&name), moved(&name)) {
+        document.set_name(&new_name);
+        self.modified.insert(new_name, document);
+      }
+    }
+  }
+
+  // reads a line of text on the status bar, None if it is left with Esc or empty
+  fn prompt(&mut self, question: &str, initial: &str) -> Option<String> {
+    let mut text = String::from(initial);
+    loop {
+      self.status_bar.message = format!("{}{}", question, text);
+      self.render();
+      match self.read_event() {
+        char_key!(key) | char_upper_key!(key) => text.push(key),
+        Event::Paste(pasted) => text.extend(pasted.chars().take_while(|key| *key != '\n' && *key != '\r')),
+        special_key!(KeyCode::Backspace) => {
+          text.pop();
+        },
+        special_key!(KeyCode::Enter) => break,
+        special_key!(KeyCode::Esc) => {
+          text.clear();
+          break
+        },
+        _ => ()
+      }
+    }
+    self.status_bar.message.clear();
+    Some(text.trim().to_string()).filter(|text| !text.is_empty())
+  }
+
+  // opens the document with the server for its language, starting that first
+  fn attach_lsp(&mut self) {
+    for client in self.servers.iter_mut() {
+      let _ = client.close();
+    }
+    let language = match self.file.language() {
+      Some(language) if !self.file.scratch && !self.file.name().is_empty() => language,
+      _ => return
+    };
+    if !self.servers.iter().any(|client| client.language == language) && !self.failed_servers.contains(&language) {
+      let started = lsp::server_config(language)
+        .map(|config| LspClient::start(language, &config).map_err(|why| format!("{}: {}", config.command, why)));
+      match started {
+        Some(Ok(client)) => self.servers.push(client),
+        Some(Err(why)) => {
+          self.failed_servers.push(language);
+          self.status_bar.message = why
+        },
+        None => self.failed_servers.push(language)
+      }
+    }
+    let lines = self.file.rows.iter().map(|row| row.content().to_string()).collect();
+    if let Some(client) = self.servers.iter_mut().find(|client| client.language == language && client.is_running()) {
+      let _ = client.open(&self.file.file_name, lines);
+    }
+    self.show_lsp_diagnostics()
+  }
+
+  // puts what the servers last reported about the open file on it, turning
+  // their UTF-16 columns into the document's
+  fn show_lsp_diagnostics(&mut self) {
+    let reported: Vec<lsp::Diagnostic> = self.servers
+      .iter()
+      .flat_map(|client| client.diagnostics.iter())
+      .filter(|(file, _)| self.is_open_file(file))
+      .flat_map(|(_, diagnostics)| diagnostics.iter().cloned())
+      .collect();
+    let column = |(row, utf16): (usize, usize)| {
+      (row, self.file.get_row(row).map(|row| lsp::to_column(row.content(), utf16)).unwrap_or(0))
+    };
+    let diagnostics = reported.into_iter().map(|diagnostic| Diagnostic {
+      start: column(diagnostic.start),
+      end: column(diagnostic.end),
+      severity: diagnostic.severity,
+      message: diagnostic.message,
+      source: "lsp"
+    }).collect();
+    self.file.diagnostics.set("lsp", diagnostics)
+  }
+
+  fn lsp_client(&mut self) -> Option<&mut LspClient> {
+    let file_name = &self.file.file_name;
+    self.servers.iter_mut().find(|client| client.is_running() && client.has_document(file_name))
+  }
+
+  fn sync_lsp(&mut self) {
+    let lines: Vec<&str> = self.file.rows.iter().map(|row| row.content()).collect();
+    let file_name = &self.file.file_name;
+    if let Some(client) = self.servers.iter_mut().find(|client| client.is_running() && client.has_document(file_name)) {
+      let _ = client.sync(&lines);
+    }
+  }
+
+  // asks the server of the open file about the cursor position, the answer
+  // is acted on when it arrives
+  fn lsp_request<F: FnOnce(&mut LspClient, Position) -> std::io::Result<()>>(&mut self, request: F) {
+    self.sync_lsp();
+    let pos = self.cursor();
+    let sent = match self.lsp_client() {
+      Some(client) => request(client, pos).map_err(|why| why.to_string()),
+      None => Err(String::from("no language server for this file"))
+    };
+    if let Err(why) = sent {
+      self.status_bar.message = why
+    }
+  }
+
+  fn receive_lsp(&mut self) {
+    let replies: Vec<LspReply> = self.servers.iter_mut().flat_map(LspClient::receive).collect();
+    for reply in replies {
+      self.handle_lsp_reply(reply)
+    }
+  }
+
+  fn handle_lsp_reply(&mut self, reply: LspReply) {
+    match reply {
+      LspReply::Hover(text) => {
+        // the status bar has room for the gist, code fences and blank lines go
+        let lines: Vec<&str> = text
+          .lines()
+          .map(str::trim)
+          .filter(|line| !line.is_empty() && !line.starts_with("```"))
+          .collect();
+        self.status_bar.message = if lines.is_empty() { String::from("no information") } else { lines.join("  ") }
+      },
+      LspReply::Definition(locations) => match locations.into_iter().next() {
+        Some(location) => {
+          self.record_jump();
+          self.goto_lsp_location(location)
+        },
+        None => self.status_bar.message = String::from("no definition found")
+      },
+      LspReply::References(locations) => {
+        if locations.is_empty() {
+          self.status_bar.message = String::from("no references found");
+          return
+        }
+        let entries = self.lsp_entries(locations.into_iter().map(|location| (location, String::new())).collect());
+        let found = entries.len();
+        self.quickfix.set(entries);
+        if self.open_quickfix() {
+          self.status_bar.message = format!("{} references", found)
+        }
+      },
+      LspReply::Rename(files) => self.apply_workspace_edit(files),
+      LspReply::Completion(items) => self.open_completion_picker(items),
+      LspReply::Diagnostics(file) => {
+        if self.is_open_file(&file) {
+          self.show_lsp_diagnostics()
+        }
+      },
+      LspReply::Message(text) => self.status_bar.message = text
+    }
+  }
+
+  // whether 'file' names the open document, however either was spelled
+  fn is_open_file(&self, file: &str) -> bool {
+    file == self.file.file_name || matches!(
+      (Path::new(file).canonicalize(), Path::new(&self.file.file_name).canonicalize()),
+      (Ok(first), Ok(second)) if first == second
+    )
+  }
+
+  fn file_lines(&self, file: &str) -> Vec<String> {
+    self.buffer_lines(file).unwrap_or_default()
+  }
+
+  // the lines of 'file' as they are being edited, which may not be on disk yet
+  fn buffer_lines(&self, file: &str) -> Result<Vec<String>, String> {
+    let document = if self.is_open_file(file) { Some(&self.file) } else { self.modified.get(file) };
+    match document {
+      Some(document) => Ok(document.rows.iter().map(|row| row.content().to_string()).collect()),
+      None => std::fs::read_to_string(file)
+        .map(|content| content.lines().map(String::from).collect())
+        .map_err(|why| format!("{}: {}", file, why))
+    }
+  }
+
+  fn goto_lsp_location(&mut self, location: LspLocation) {
+    if !self.is_open_file(&location.file) && !self.open_file(&location.file) {
+      return
+    }
+    let row = std::cmp::min(location.start.0, self.file.len() - 1);
+    let column = lsp::to_column(self.file.rows[row].content(), location.start.1);
+    self.jump_to(row, column)
+  }
+
+  // quickfix entries for server locations, the text of each being the line
+  // it is on unless one is given
+  fn lsp_entries(&self, locations: Vec<(LspLocation, String)>) -> Vec<QuickfixEntry> {
+    let mut files: HashMap<String, Vec<String>> = HashMap::new();
+    locations.into_iter().map(|(location, text)| {
+      let lines = files.entry(location.file.clone()).or_insert_with(|| self.file_lines(&location.file));
+      let line = lines.get(location.start.0).map(String::as_str).unwrap_or("");
+      QuickfixEntry {
+        column: lsp::to_column(line, location.start.1),
+        row: location.start.0,
+        text: if text.is_empty() { line.trim().to_string() } else { text },
+        file: location.file
+      }
+    }).collect()
+  }
+
+  // :Diagnostics, what every server has reported, in the quickfix list
+  fn list_diagnostics(&mut self) {
+    let mut found = Vec::new();
+    for client in &self.servers {
+      for (file, diagnostics) in &client.diagnostics {
+        for diagnostic in diagnostics {
+          let location = LspLocation { file: file.clone(), start: diagnostic.start };
+          found.push((location, format!("{}: {}", diagnostic.severity.label(), diagnostic.message)))
+        }
+      }
+    }
+    found.sort_by(|(first, _), (second, _)| first.file.cmp(&second.file).then(first.start.cmp(&second.start)));
+    let entries = self.lsp_entries(found);
+    self.quickfix.set(entries);
+    self.open_quickfix();
+  }
+
+  // a rename from the server: the open document is edited as one undo step,
+  // other files are kept with the change unwritten until the editor quits;
+  // every file is checked first so none are left half renamed
+  fn apply_workspace_edit(&mut self, files: Vec<(String, Vec<TextEdit>)>) {
+    let mut changes = Vec::new();
+    for (file, edits) in files {
+      let before = match self.buffer_lines(&file) {
+        Ok(before) => before,
+        Err(why) => {
+          self.status_bar.message = why;
+          return
+        }
+      };
+      let after = lsp::apply_edits(&before, &edits);
+      changes.push(FileChange { file, before, after, hunks: Vec::new(), replaced: edits.len() })
+    }
+    let failed = changes
+      .iter()
+      .filter(|change| !self.is_open_file(&change.file))
+      .map(|change| self.check_file_change(change))
+      .find_map(Result::err);
+    if let Some(why) = failed {
+      self.status_bar.message = why;
+      return
+    }
+    for change in &changes {
+      if self.is_open_file(&change.file) {
+        let rows: Vec<Row> = change.after.iter().map(|line| Row::from(line.as_str())).collect();
+        if let Some(node) = HistoryNode::between(&self.file.rows, &rows) {
+          let start = node.altered_rows.start;
+          let old = start..(start + node.rows.len());
+          self.edit_rows(old.clone(), |document| document.splice_rows(old, rows[node.altered_rows].to_vec()))
+        }
+        continue
+      }
+      if !self.modified.contains_key(&change.file) {
+        match Document::open(&change.file) {
+          Ok(document) => {
+            self.modified.insert(change.file.clone(), document);
+          },
+          Err(why) => {
+            self.status_bar.message = format!("{}: {}", change.file, why);
+            return
+          }
+        }
+      }
+      if let Err(why) = self.apply_file_change(change) {
+        self.status_bar.message = why;
+        return
+      }
+    }
+    self.status_bar.message = format!("changed {} files", changes.len())
+  }
+
+  fn open_completion_picker(&mut self, items: Vec<CompletionItem>) {
+    // the answer came too late, typing is over
+    if self.mode != EditorMode::Insert {
+      return
+    }
+    let (row, column) = self.cursor();
+    let start = completion::word_start(&motion::graphemes(&self.file, row), column);
+    let typed = motion::graphemes(&self.file, row)[start..column].concat();
+    let choices = items
+      .iter()
+      .enumerate()
+      .map(|(idx, item)| PickerItem::new(&item.label, &item.detail, &idx.to_string()))
+      .collect();
+    self.completions = items;
+    let mut picker = Picker::new(PickerKind::Completion, choices);
+    picker.query = typed;
+    self.open_picker(picker)
+  }
+
+  // replaces the word typed before the cursor (or what the server said to
+  // replace) with the completion
+  fn insert_completion(&mut self, completion: &CompletionItem) {
+    let (row, column) = self.cursor();
+    let start = match completion.start {
+      Some((start_row, utf16)) if start_row == row => std::cmp::min(lsp::to_column(self.file.rows[row].content(), utf16), column),
+      _ => completion::word_start(&motion::graphemes(&self.file, row), column)
+    };
+    self.complete_with(start, &completion.text)
+  }
+
+  // replaces what was typed from 'start' up to the cursor with 'text',
+  // recorded for '.' like typing it would be
+  fn complete_with(&mut self, start: usize, text: &str) {
+    let column = self.cursor().1;
+    let keys = std::iter::repeat_n(InsertKey::Backspace, column.saturating_sub(start))
+      .chain(std::iter::once(InsertKey::Text(text.to_string())));
+    for key in keys {
+      if let Some(change) = &mut self.pending_change {
+        change.inserted.push(key.clone())
+      }
+      self.insert_key(key)
+    }
+    self.altered = true
+  }
+
+  // the words of the open file, those nearest the cursor first, then those of
+  // the other files opened this session, then the keywords of the language
+  fn completion_candidates(&mut self, row: usize) -> Vec<Candidate> {
+    let mut seen = HashSet::new();
+    let mut rows: Vec<usize> = (0..self.file.len()).collect();
+    rows.sort_by_key(|idx| (*idx as i64 - row as i64).abs());
+    let mut candidates = completion::words(rows.into_iter().map(|idx| self.file.rows[idx].content()), &mut seen);
+    let open = &self.file.file_name;
+    for file in self.recent_files.iter().filter(|file| *file != open) {
+      // one kept with changes is read as it is being edited
+      match self.modified.get(file) {
+        Some(document) => candidates.extend(completion::words(document.rows.iter().map(Row::content), &mut seen)),
+        None => candidates.extend(completion::unseen(self.file_words.get(file), CandidateKind::Word, &mut seen))
+      }
+    }
+    if let Some(language) = self.file.language() {
+      candidates.extend(completion::unseen(language.keywords(), CandidateKind::Keyword, &mut seen))
+    }
+    candidates
+  }
+
+  // Ctrl-N and Ctrl-P: the popup for the path or word before the cursor
+  fn open_completion(&mut self, asked: bool) {
+    let (row, column) = self.cursor();
+    let graphemes = motion::graphemes(&self.file, row);
+    let (start, dir) = completion::prefix(&graphemes, column);
+    let query = graphemes[start..column].concat();
+    let candidates = match dir {
+      Some(dir) => completion::paths(&dir),
+      None => self.completion_candidates(row)
+    };
+    let mut popup = Popup::new(row, start, candidates);
+    popup.refilter(&query);
+    if popup.matches.is_empty() {
+      if asked {
+        self.status_bar.message = String::from("no completions")
+      }
+      return
+    }
+    self.popup = Some(popup)
+  }
+
+  // narrows the popup as what it completes is typed and closes it once the
+  // cursor leaves that; with autocomplete set, a word long enough or a slash
+  // in a path opens it
+  fn update_completion(&mut self, typed: Option<char>) {
+    let (row, column) = self.cursor();
+    let graphemes = motion::graphemes(&self.file, row);
+    let (start, dir) = completion::prefix(&graphemes, column);
+    let query = graphemes[start..column].concat();
+    if let Some(popup) = &mut self.popup {
+      if popup.row == row && popup.start == start {
+        popup.refilter(&query);
+        if !popup.matches.is_empty() {
+          return
+        }
+      }
+      self.popup = None
+    }
+    let wanted = match typed {
+      Some('/') => dir.is_some(),
+      Some(key) => dir.is_none() && (key.is_alphanumeric() || key == '_') && query.chars().count() >= AUTOCOMPLETE_LENGTH,
+      None => false
+    };
+    if self.autocomplete && wanted {
+      self.open_completion(false)
+    }
+  }
+
+  fn accept_completion(&mut self) {
+    let popup = match self.popup.take() {
+      Some(popup) => popup,
+      None => return
+    };
+    if let Some(candidate) = popup.selected_candidate() {
+      let text = candidate.text.clone();
+      self.complete_with(popup.start, &text);
+      // a directory goes on to what is inside it
+      if text.ends_with('/') {
+        self.update_completion(Some('/'))
+      }
+    }
+  }
+
+  // draws the popup under the cursor (over it when there is no room below),
+  // lined up with the start of what it completes
+  fn render_completion(&self) {
+    let popup = match &self.popup {
+      Some(popup) if self.mode == EditorMode::Insert => popup,
+      _ => return
+    };
+    let shown = std::cmp::min(popup.matches.len(), MAX_POPUP_ROWS);
+    let text_rows = self.terminal.height.saturating_sub(1) as usize;
+    let cursor_row = self.position.0 as usize;
+    let top = if cursor_row + 1 + shown <= text_rows {
+      cursor_row + 1
+    } else if cursor_row >= shown {
+      cursor_row - shown
+    } else {
+      return
+    };
+    let first = (popup.selected + 1).saturating_sub(shown);
+    let label_width = popup.matches.iter().map(|found| popup.candidate(found).text.chars().count()).max().unwrap_or(0);
+    let terminal_width = self.terminal.width as usize;
+    let width = std::cmp::min(label_width + 10, terminal_width);
+    let left = std::cmp::min(self.buffer as usize + 1 + popup.start, terminal_width - width);
+    let label_width = width.saturating_sub(10);
+    let mut stdout = stdout();
+    for (line, found) in popup.matches.iter().enumerate().skip(first).take(shown) {
+      let candidate = popup.candidate(found);
+      let selected = line == popup.selected;
+      let (background, foreground) = if selected { (Color::White, Color::Black) } else { (Color::DarkGrey, Color::White) };
+      let _ = execute!(
+        stdout,
+        MoveTo(left as u16, (top + line - first) as u16),
+        SetBackgroundColor(background),
+        Print(" ")
+      );
+      let label: Vec<char> = candidate.text.chars().take(label_width).collect();
+      for (idx, ch) in label.iter().enumerate() {
+        let color = if found.positions.contains(&idx) { Color::DarkYellow } else { foreground };
+        let _ = execute!(stdout, SetForegroundColor(color), Print(ch));
+      }
+      let _ = execute!(
+        stdout,
+        SetForegroundColor(if selected { Color::DarkGrey } else { Color::Grey }),
+        Print(format!("{:pad$} {:>7} ", "", candidate.kind.tag(), pad = label_width - label.len())),
+        ResetColor
+      );
+    }
+  }
+
+  // :grep pattern [path], a pattern containing spaces can be given as /pattern/
+  fn grep(&mut self, args: &str) {
+    let args = args.trim();
+    let (pattern, root) = if args.starts_with('/') {
+      let chars: Vec<char> = args.chars().collect();
+      let mut idx = 1;
+      let pattern = substitute::parse_part(&chars, &mut idx, '/');
+      (pattern, chars[std::cmp::min(idx, chars.len())..].iter().collect::<String>())
+    } else {
+      match args.split_once(char::is_whitespace) {
+        Some((pattern, root)) => (pattern.to_string(), root.to_string()),
+        None => (args.to_string(), String::new())
+      }
+    };
+    if pattern.is_empty() {
+      self.status_bar.message = String::from("usage: grep pattern [path]");
+      return
+    }
+    let regex = match search::compile(&pattern, &self.search_options) {
+      Ok(regex) => regex,
+      Err(why) => {
+        self.status_bar.message = why;
+        return
+      }
+    };
+    let root = if root.trim().is_empty() { "." } else { root.trim() };
+    let entries = grep::grep(&regex, root);
+    if entries.is_empty() {
+      self.status_bar.message = format!("no matches for {}", pattern);
+      return
+    }
+    let found = entries.len();
+    self.quickfix.set(entries);
+    if self.open_quickfix() {
+      self.status_bar.message = format!("{} matches", found)
+    }
+  }
+
+  // :make [args], the build run in the background once the open file is written
+  fn make(&mut self, args: &str) {
+    if self.build.is_some() {
+      self.status_bar.message = String::from("a build is already running");
+      return
+    }
+    if !self.write_all() {
+      return
+    }
+    let program = self.make_program.as_deref().unwrap_or_else(|| make::default_program());
+    let command = format!("{} {}", program, args.trim()).trim().to_string();
+    match Build::start(&command) {
+      Ok(build) => {
+        self.status_bar.message = format!("running {}", command);
+        self.build = Some(build)
+      },
+      Err(why) => self.status_bar.message = format!("{}: {}", command, why)
+    }
+  }
+
+  // once the build is over what it reported becomes the quickfix list, the
+  // first entry visited unless something else is being done
+  fn receive_build(&mut self) {
+    let status = match self.build.as_mut().and_then(Build::receive) {
+      Some(status) => status,
+      None => return
+    };
+    let build = self.build.take().unwrap();
+    self.build_errors = make::parse(&build.output);
+    self.show_build_diagnostics();
+    self.quickfix.set(self.build_errors.iter().map(|error| error.entry.clone()).collect());
+    if self.build_errors.is_empty() {
+      self.status_bar.message = match (status.success(), build.output.last()) {
+        (true, _) => format!("{} succeeded", build.command),
+        (false, Some(last)) => format!("{} failed: {}", build.command, last),
+        (false, None) => format!("{} failed", build.command)
+      };
+      return
+    }
+    let warnings = self.build_errors.iter().filter(|error| error.severity != Severity::Error).count();
+    let summary = format!("{}: {} errors, {} warnings", build.command, self.build_errors.len() - warnings, warnings);
+    if self.mode == EditorMode::Normal {
+      let entry = self.quickfix.select(0).cloned();
+      self.open_quickfix_entry(entry)
+    } else {
+      self.status_bar.message = summary
+    }
+  }
+
+  // puts what the last build reported about the open file on it
+  fn show_build_diagnostics(&mut self) {
+    let diagnostics = self.build_errors
+      .iter()
+      .filter(|error| self.is_open_file(&error.entry.file))
+      .map(|error| Diagnostic {
+        start: (error.entry.row, error.entry.column),
+        end: (error.entry.row, error.entry.column + 1),
+        severity: error.severity,
+        message: error.message.clone(),
+        source: "make"
+      })
+      .collect();
+    self.file.diagnostics.set("make", diagnostics)
+  }
+
+  // shows the quickfix list as a buffer of its own, one entry per row
+  fn open_quickfix(&mut self) -> bool {
+    if self.quickfix.is_empty() {
+      self.status_bar.message = String::from("no results");
+      return false
+    }
+    if !self.leave_document() {
+      return false
+    }
+    self.show_document(Document::scratch(QUICKFIX_NAME, &self.quickfix.lines()));
+    self.showing_quickfix = true;
+    self.jump_to(self.quickfix.current, 0);
+    true
+  }
+
+  fn open_quickfix_entry(&mut self, entry: Option<QuickfixEntry>) {
+    let entry = match entry {
+      Some(entry) => entry,
+      None => {
+        self.status_bar.message = String::from("no more items");
+        return
+      }
+    };
+    self.record_jump();
+    if self.goto_location((entry.file, (entry.row, entry.column))) {
+      self.status_bar.message = format!("({} of {}) {}", self.quickfix.current + 1, self.quickfix.entries.len(), entry.text)
+    }
+  }
+
+  // :cdo and :cfdo, a substitution on the lines of the quickfix entries (or on
+  // their whole files) that is applied once its diff has been accepted
+  fn replace_in_files(&mut self, args: &str, whole_files: bool) {
+    let substitute = match substitute::parse(args, &self.search_options) {
+      Some(Ok(substitute)) => substitute,
+      Some(Err(why)) => {
+        self.status_bar.message = why;
+        return
+      },
+      None => {
+        self.status_bar.message = String::from("usage: cdo s/pattern/replacement/[flags]");
+        return
+      }
+    };
+    if self.quickfix.is_empty() {
+      self.status_bar.message = String::from("no results");
+      return
+    }
+    if !self.leave_document() {
+      return
+    }
+    let changes = match replace::plan(&substitute, &self.quickfix.entries, whole_files, |file| self.buffer_lines(file)) {
+      Ok(changes) if changes.is_empty() => {
+        self.status_bar.message = format!("pattern not found: {}", substitute.regex.as_str());
+        return
+      },
+      Ok(changes) => changes,
+      Err(why) => {
+        self.status_bar.message = why;
+        return
+      }
+    };
+    let (origin, (row, column), showing_quickfix) = (self.file.file_name.clone(), self.cursor(), self.showing_quickfix);
+    let replaced: usize = changes.iter().map(|change| change.replaced).sum();
+    let prompt = format!("apply {} replacements in {} files? (y/n)", replaced, changes.len());
+    self.show_document(Document::scratch(PREVIEW_NAME, &replace::preview(&changes)));
+    let accepted = loop {
+      self.status_bar.message = prompt.clone();
+      self.render();
+      match self.read_event() {
+        char_key!('y') => break true,
+        char_key!('n') | char_key!('q') | special_key!(KeyCode::Esc) => break false,
+        char_key!('j') | special_key!(KeyCode::Down) => self.scroll(Direction::Down),
+        char_key!('k') | special_key!(KeyCode::Up) => self.scroll(Direction::Up),
+        _ => ()
+      }
+    };
+    // every file is checked before any is changed, so none are left half done
+    let failed = if accepted {
+      changes.iter().map(|change| self.check_file_change(change)).find_map(Result::err)
+        .or_else(|| changes.iter().map(|change| self.apply_file_change(change)).find_map(Result::err))
+    } else {
+      None
+    };
+    if showing_quickfix {
+      self.open_quickfix();
+    } else if self.open_file(&origin) {
+      self.jump_to(row, column)
+    }
+    self.status_bar.message = match failed {
+      Some(why) => why,
+      None if accepted => format!("{} replacements in {} files", replaced, changes.len()),
+      None => String::from("nothing replaced")
+    }
+  }
+
+  // whether a planned change can still be made: its file is as it was planned
+  // from and, unless it is kept unwritten, can be written
+  fn check_file_change(&self, change: &FileChange) -> Result<(), String> {
+    if self.buffer_lines(&change.file)? != change.before {
+      return Err(format!("{} changed since the preview", change.file))
+    }
+    if !self.modified.contains_key(&change.file) {
+      std::fs::OpenOptions::new().write(true).open(&change.file).map_err(|why| format!("{}: {}", change.file, why))?;
+    }
+    Ok(())
+  }
+
+  // makes one file's planned change, kept as a single undo step for the file;
+  // a file left with changes of its own stays unwritten along with them
+  fn apply_file_change(&mut self, change: &FileChange) -> Result<(), String> {
+    let write = !self.modified.contains_key(&change.file);
+    let mut opened = None;
+    let document = match self.modified.get_mut(&change.file) {
+      Some(document) => document,
+      None => opened.insert(Document::open(&change.file).map_err(|why| format!("{}: {}", change.file, why))?)
+    };
+    if document.rows.iter().map(|row| row.content()).ne(change.before.iter().map(String::as_str)) {
+      return Err(format!("{} changed since the preview", change.file))
+    }
+    let after: Vec<Row> = change.after.iter().map(|line| Row::from(line.as_str())).collect();
+    let node = match HistoryNode::between(&document.rows, &after) {
+      Some(node) => node,
+      None => return Ok(())
+    };
+    let start = node.altered_rows.start;
+    document.splice_rows(start..(start + node.rows.len()), after[node.altered_rows.clone()].to_vec());
+    if write {
+      document.save().map_err(|why| format!("{}: {}", change.file, why))?;
+    }
+    for (range, new_len) in document.take_row_edits() {
+      self.marks.adjust(&change.file, range, new_len)
+    }
+    self.histories.entry(change.file.clone()).or_insert_with(History::new).push(node);
+    Ok(())
+  }
+
+  // commands spelled out as a word, such as :grep; false if 'name' is none of them
+  fn run_named_command(&mut self, command: &str) -> bool {
+    let (name, args) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+    let count = args.trim().parse::<usize>().ok();
+    match name {
+      "noh" | "nohlsearch" => self.highlight_search = false,
+      "grep" => self.grep(args),
+      "make" => self.make(args),
+      "copen" => {
+        self.open_quickfix();
+      },
+      "cn" | "cnext" => {
+        let entry = self.quickfix.next(count.unwrap_or(1)).cloned();
+        self.open_quickfix_entry(entry)
+      },
+      "cp" | "cprev" | "cprevious" => {
+        let entry = self.quickfix.prev(count.unwrap_or(1)).cloned();
+        self.open_quickfix_entry(entry)
+      },
+      "Files" => self.open_file_picker(),
+      "Buffers" => self.open_buffer_picker(),
+      "History" => self.open_recent_picker(),
+      "Symbols" => self.open_symbol_picker(),
+      "Commands" => self.open_command_picker(),
+      "Ex" | "Explore" => self.explore_command(args.trim()),
+      "Rename" => {
+        let name = args.trim().to_string();
+        if name.is_empty() {
+          self.status_bar.message = String::from("usage: Rename new_name")
+        } else {
+          self.lsp_request(|client, pos| client.rename(pos, &name))
+        }
+      },
+      "Diagnostics" => self.list_diagnostics(),
+      "cdo" => self.replace_in_files(args, false),
+      "cfdo" => self.replace_in_files(args, true),
+      "cc" => {
+        let idx = count.map(|line| line.saturating_sub(1)).unwrap_or(self.quickfix.current);
+        let entry = self.quickfix.select(idx).cloned();
+        self.open_quickfix_entry(entry)
+      },
+      _ => return false
+    }
+    true
+  }
+
+  // Ctrl-P, the files under the working directory, listed while they are found
+  fn open_file_picker(&mut self) {
+    self.open_picker(Picker::with_source(PickerKind::Files, picker::index_files(".")))
+  }
+
+  fn open_buffer_picker(&mut self) {
+    let items = self.recent_files
+      .iter()
+      .map(|name| PickerItem::new(name, if *name == self.file.file_name { "%" } else { "" }, name))
+      .collect();
+    self.open_picker(Picker::new(PickerKind::Buffers, items))
+  }
+
+  fn open_recent_picker(&mut self) {
+    let items = self.old_files
+      .iter()
+      .filter(|file| Path::new(file).is_file())
+      .map(|file| {
+        let name = recent::display(file);
+        PickerItem::new(&name, "", &name)
+      })
+      .collect();
+    self.open_picker(Picker::new(PickerKind::Recent, items))
+  }
+
+  fn open_symbol_picker(&mut self) {
+    let items = textobject::functions(&self.file)
+      .into_iter()
+      .map(|((row, column), name)| PickerItem::new(&name, &format!("line {}", row + 1), &format!("{}:{}", row, column)))
+      .collect();
+    self.open_picker(Picker::new(PickerKind::Symbols, items))
+  }
+
+  fn open_command_picker(&mut self) {
+    let items = COMMANDS
+      .iter()
+      .map(|(command, description)| PickerItem::new(command.trim_start_matches(':').trim_end(), description, command))
+      .collect();
+    self.open_picker(Picker::new(PickerKind::Commands, items))
+  }
+
+  fn open_picker(&mut self, picker: Picker) {
+    if picker.len() == 0 && !picker.is_loading() {
+      self.status_bar.message = format!("{}: nothing to pick from", picker.kind.title());
+      return
+    }
+    self.picker = Some(picker);
+    self.refilter_picker();
+    self.set_mode(EditorMode::Picker)
+  }
+
+  fn refilter_picker(&mut self) {
+    let recent_files = &self.recent_files;
+    if let Some(picker) = &mut self.picker {
+      // files opened lately rank above others that match about as well
+      picker.refilter(|item| recent_files
+        .iter()
+        .position(|name| *name == item.value)
+        .map(|idx| 20 - 2 * std::cmp::min(idx, 10) as i64)
+        .unwrap_or(0))
+    }
+  }
+
+  // takes what the picker's background source has found since the last time
+  fn receive_picker(&mut self) {
+    if self.picker.as_mut().is_some_and(|picker| picker.receive()) {
+      self.refilter_picker()
+    }
+  }
+
+  fn handle_picker(&mut self) {
+    self.receive_picker();
+    // while the index is still growing, redraw it rather than wait for a key
+    if self.picker.as_ref().is_some_and(|picker| picker.is_loading())
+      && self.pending_events.is_empty()
+      && !poll(std::time::Duration::from_millis(50)).unwrap_or(false) {
+      return
+    }
+    let event = self.read_event();
+    let picker = match &mut self.picker {
+      Some(picker) => picker,
+      None => return self.set_mode(EditorMode::Normal)
+    };
+    match event {
+      char_key!(key) | char_upper_key!(key) => {
+        picker.query.push(key);
+        self.refilter_picker()
+      },
+      Event::Paste(text) => {
+        picker.query.extend(text.chars().take_while(|key| *key != '\n' && *key != '\r'));
+        self.refilter_picker()
+      },
+      special_key!(KeyCode::Backspace) => {
+        picker.query.pop();
+        self.refilter_picker()
+      },
+      special_key!(KeyCode::Up) | ctrl_key!('p') | ctrl_key!('k') => picker.move_selection(false),
+      special_key!(KeyCode::Down) | special_key!(KeyCode::Tab) | ctrl_key!('n') | ctrl_key!('j') => picker.move_selection(true),
+      special_key!(KeyCode::Enter) => self.accept_picker(),
+      special_key!(KeyCode::Esc) => {
+        // leaving completion goes back to typing
+        let mode = if picker.kind == PickerKind::Completion { EditorMode::Insert } else { EditorMode::Normal };
+        self.picker = None;
+        self.set_mode(mode)
+      },
+      _ => ()
+    }
+  }
+
+  fn accept_picker(&mut self) {
+    self.set_mode(EditorMode::Normal);
+    let picker = match self.picker.take() {
+      Some(picker) => picker,
+      None => return
+    };
+    let item = match picker.selected_item() {
+      Some(item) => item.clone(),
+      None => return
+    };
+    match picker.kind {
+      PickerKind::Files | PickerKind::Buffers | PickerKind::Recent => {
+        if item.value != self.file.file_name {
+          self.record_jump();
+          self.open_file(&item.value);
+        }
+      },
+      PickerKind::Symbols => {
+        if let Some((row, column)) = parse_position(&item.value) {
+          self.record_jump();
+          self.jump_to(row, column)
+        }
+      },
+      PickerKind::Completion => {
+        self.set_mode(EditorMode::Insert);
+        if let Some(completion) = item.value.parse::<usize>().ok().and_then(|idx| self.completions.get(idx).cloned()) {
+          self.insert_completion(&completion)
+        }
+      },
+      PickerKind::Commands => {
+        self.status_bar.cmd = item.value.clone();
+        self.status_bar.cmd_chars = item.value.chars().count();
+        if item.value.ends_with([' ', '/']) {
+          self.set_mode(EditorMode::Command)
+        } else {
+          let _ = self.evaluate_expr();
+        }
+      }
+    }
+  }
+
+  // the lines of what the selected item refers to, from its start
+  fn picker_preview(&self, kind: PickerKind, item: &PickerItem, lines: usize) -> Vec<String> {
+    let from_document = |first: usize| self.file.rows
+      .iter()
+      .skip(first)
+      .take(lines)
+      .map(|row| row.content().replace('\t', "  "))
+      .collect();
+    match kind {
+      // the open file may have changes that are not written yet
+      PickerKind::Files | PickerKind::Buffers | PickerKind::Recent if item.value == self.file.file_name => from_document(0),
+      PickerKind::Files | PickerKind::Buffers | PickerKind::Recent => {
+        use std::io::BufRead;
+        let file = match std::fs::File::open(&item.value) {
+          Ok(file) => file,
+          Err(why) => return vec![why.to_string()]
+        };
+        let mut preview = Vec::new();
+        for line in std::io::BufReader::new(file).lines().take(lines) {
+          match line {
+            Ok(line) => preview.push(line.replace('\t', "  ")),
+            Err(_) => return vec![String::from("[binary file]")]
+          }
+        }
+        preview
+      },
+      PickerKind::Symbols => parse_position(&item.value).map(|(row, _)| from_document(row)).unwrap_or_default(),
+      PickerKind::Commands | PickerKind::Completion => Vec::new()
+    }
+  }
+
+  // draws the picker over the lower part of the screen: a title, the matches
+  // with their matched characters highlighted next to a preview, and the query
+  fn render_picker(&self) {
+    let picker = match &self.picker {
+      Some(picker) => picker,
+      None => return
+    };
+    let width = self.terminal.width as usize;
+    let available = self.terminal.height.saturating_sub(1) as usize;
+    let height = std::cmp::min(available, std::cmp::max(available / 2, 10));
+    if height < 3 {
+      return
+    }
+    let top = available - height;
+    let body = height - 2;
+    // narrow terminals have no room for a preview
+    let list_width = if width >= 60 && picker.kind.has_preview() { width / 2 } else { width };
+    let mut stdout = stdout();
+    let title = format!(" {} {}/{}{}",
+      picker.kind.title(),
+      picker.matches.len(),
+      picker.len(),
+      if picker.is_loading() { " (indexing)" } else { "" });
+    let _ = execute!(
+      stdout,
+      MoveTo(0, top as u16),
+      SetBackgroundColor(Color::White),
+      SetForegroundColor(Color::Black),
+      Print(format!("{:width$}", title.chars().take(width).collect::<String>(), width = width)),
+      ResetColor
+    );
+    let first = (picker.selected + 1).saturating_sub(body);
+    let preview = match picker.selected_item() {
+      Some(item) if list_width < width => self.picker_preview(picker.kind, item, body),
+      _ => Vec::new()
+    };
+    for line in 0..body {
+      let _ = execute!(
+        stdout,
+        MoveTo(0, (top + 1 + line) as u16),
+        Clear(ClearType::CurrentLine)
+      );
+      let mut written = 0;
+      if let Some(found) = picker.matches.get(first + line) {
+        let item = picker.item(found);
+        let selected = first + line == picker.selected;
+        let _ = execute!(stdout, Print(if selected { "> " } else { "  " }));
+        written += 2;
+        for (idx, ch) in item.label.chars().enumerate().take(list_width.saturating_sub(written + 1)) {
+          let color = if found.positions.contains(&idx) { Color::Yellow } else { Color::Reset };
+          let attribute = if selected { Attribute::Bold } else { Attribute::NormalIntensity };
+          let _ = execute!(
+            stdout,
+            SetForegroundColor(color),
+            SetAttribute(attribute),
+            Print(ch),
+            SetAttribute(Attribute::Reset),
+            ResetColor
+          );
+          written += 1
+        }
+        if !item.detail.is_empty() && written + 2 < list_width {
+          let detail: String = item.detail.chars().take(list_width - written - 2).collect();
+          written += detail.chars().count() + 1;
+          let _ = execute!(
+            stdout,
+            SetForegroundColor(Color::DarkGrey),
+            Print(format!(" {}", detail)),
+            ResetColor
+          );
+        }
+      }
+      if list_width < width {
+        let text: String = preview.get(line).map(|text| text.chars().take(width - list_width - 2).collect()).unwrap_or_default();
+        let _ = execute!(
+          stdout,
+          Print(format!("{:pad$}", "", pad = list_width.saturating_sub(written))),
+          SetForegroundColor(Color::DarkGrey),
+          Print("│ "),
+          ResetColor,
+          Print(text)
+        );
+      }
+    }
+    let prompt_row = (top + height - 1) as u16;
+    let query: String = picker.query.chars().take(width.saturating_sub(3)).collect();
+    let _ = execute!(
+      stdout,
+      MoveTo(0, prompt_row),
+      Clear(ClearType::CurrentLine),
+      Print(format!("> {}", query)),
+      MoveTo(2 + query.chars().count() as u16, prompt_row),
+      Show
+    );
+  }
+
+  fn stop_recording(&mut self) {
+    if let Some((name, mut recorded)) = self.recording.take() {
+      // the q that ended the recording was recorded as well
+      recorded.pop();
+      self.registers.record(name, recorded)
+    }
+    self.status_bar.recording = None
+  }
+
+  fn replay_macro(&mut self, name: char, count: usize) {
+    let name = if name == '@' {
+      match self.last_replayed {
+        Some(name) => name,
+        None => return
+      }
+    } else {
+      name
+    };
+    let events = match self.registers.get(name) {
+      Some(content) => keys::decode(&content.to_text()),
+      None => return
+    };
+    if self.replay_depth >= MAX_REPLAY_DEPTH {
+      return
+    }
+    self.last_replayed = Some(name);
+    self.as_single_edit(|editor| {
+      for _ in 0..count {
+        editor.dispatch_events(events.clone())
+      }
+    })
+  }
+
+  // feeds events through the regular mode handlers until they run out
+  fn dispatch_events(&mut self, events: Vec<Event>) {
+    let saved = std::mem::replace(&mut self.pending_events, events.into());
+    self.replay_depth += 1;
+    while !self.pending_events.is_empty() && !self._quit {
+      self.dispatch()
+    }
+    self.replay_depth -= 1;
+    self.pending_events = saved
+  }
+
+  // runs 'edit' and records everything it changed as a single undo step; when
+  // 'edit' switched documents the steps stay as they were recorded, since the
+  // history now belongs to another file
+  fn as_single_edit<F: FnOnce(&mut Self)>(&mut self, edit: F) {
+    let before = self.file.rows.clone();
+    let history_len = self.history.len();
+    let file_name = self.file.file_name.clone();
+    edit(self);
+    if self.file.file_name != file_name {
+      return
+    }
+    self.history.rollback_to(history_len);
+    if let Some(node) = HistoryNode::between(&before, &self.file.rows) {
+      self.history.push(node)
+    }
+  }
+
+  fn span_content(&self, span: &Span) -> RegisterContent {
+    match span {
+      Span::Lines(rows) => RegisterContent::Linewise(
+        self.file.rows[rows.clone()].iter().map(|row| row.content().to_string()).collect()
+      ),
+      Span::Chars(start, end) => RegisterContent::Charwise(self.file.text_range(*start, *end))
+    }
+  }
+
+  fn apply_operator(&mut self, op: Operator, span: Span, register: Option<char>) {
+    let rows = match &span {
+      Span::Lines(rows) => rows.clone(),
+      Span::Chars(start, end) => start.0..(end.0 + 1)
+    };
+    match (op, span) {
+      (Operator::Yank, span) => {
+        let content = self.span_content(&span);
+        self.registers.yank(register, content);
+        self.sync_clipboard(register);
+        match span {
+          Span::Lines(rows) => self.jump_to(rows.start, self.cursor().1),
+          Span::Chars(start, _) => self.jump_to(start.0, start.1)
+        }
+      },
+      (Operator::Delete, span) | (Operator::Change, span) => {
+        let content = self.span_content(&span);
+        self.registers.delete(register, content);
+        self.sync_clipboard(register);
+        match span {
+          Span::Lines(lines) if op == Operator::Change => {
+            // keep the first line's indentation to type after
+            let indent = self.file.rows[lines.start].content()
+              .chars()
+              .take_while(|ch| ch.is_whitespace())
+              .collect::<String>();
+            let indent_len = indent.chars().count();
+            self.edit_rows(rows, |file| file.splice_rows(lines.clone(), vec![Row::from(indent.as_str())]));
+            self.jump_to(lines.start, indent_len)
+          },
+          Span::Lines(lines) => {
+            self.edit_rows(rows, |file| {
+              file.remove_rows(lines.clone());
+            });
+            let row = std::cmp::min(lines.start, self.file.len() - 1);
+            self.jump_to(row, motion::first_non_blank(&self.file, row))
+          },
+          Span::Chars(start, end) => {
+            self.edit_rows(rows, |file| {
+              file.delete_text(start, end);
+            });
+            self.jump_to(start.0, start.1)
+          }
+        }
+        if op == Operator::Change {
+          self.set_mode(EditorMode::Insert)
+        }
+      },
+      (Operator::Indent, _) | (Operator::Dedent, _) => {
+        let first = rows.start;
+        self.edit_rows(rows.clone(), |file| {
+          for row_no in rows {
+            let row = file.get_row_mut(row_no).unwrap();
+            if op == Operator::Indent {
+              if row.len() > 0 {
+                row.insert_str(0, "  ")
+              }
+            } else {
+              let leading = row.content().chars().take(2).take_while(|ch| *ch == ' ').count();
+              row.remove(0..leading);
+            }
+          }
+        });
+        self.jump_to(first, motion::first_non_blank(&self.file, first))
+      },
+      (Operator::Reindent, _) => {
+        let first = rows.start;
+        let language = self.file.language();
+        let start = self.file.item_start(first);
+        let lines = indent::lines(language, &self.file.rows[start..rows.end]);
+        let indents = indent::reindent(language, &lines, (first - start)..(rows.end - start));
+        self.edit_rows(rows.clone(), |file| {
+          for (row_no, indent) in rows.zip(indents) {
+            let row = file.get_row_mut(row_no).unwrap();
+            let leading = indentation(row);
+            let blank = leading == row.len();
+            row.remove(0..leading);
+            if !blank {
+              row.insert_str(0, &" ".repeat(indent))
+            }
+          }
+        });
+        self.jump_to(first, motion::first_non_blank(&self.file, first))
+      },
+      (Operator::Lowercase, span) | (Operator::Uppercase, span) => {
+        let convert = |text: &str| if op == Operator::Lowercase { text.to_lowercase() } else { text.to_uppercase() };
+        let (start, end) = match span {
+          Span::Lines(lines) => ((lines.start, 0), (lines.end - 1, self.file.rows[lines.end - 1].len())),
+          Span::Chars(start, end) => (start, end)
+        };
+        self.edit_rows(rows, |file| {
+          let text = convert(&file.delete_text(start, end));
+          file.insert_text(start, &text);
+        });
+        self.jump_to(start.0, start.1)
+      }
+    }
+  }
+
+  fn start_insert(&mut self, at: InsertAt) {
+    let (row, column) = self.cursor();
+    match at {
+      InsertAt::Cursor => (),
+      InsertAt::After => self.jump_to(row, column + 1),
+      InsertAt::LineStart => self.jump_to(row, motion::first_non_blank(&self.file, row)),
+      InsertAt::LineEnd => self.jump_to(row, self.file.rows[row].len()),
+      InsertAt::LineBelow | InsertAt::LineAbove => {
+        let at = if at == InsertAt::LineBelow { row + 1 } else { row };
+        let indent = " ".repeat(self.rule_indent(at, ""));
+        self.edit_rows(at..at, |file| file.insert_rows(at, vec![Row::from(indent.as_str())]));
+        self.jump_to(at, indent.len())
+      }
+    }
+    self.set_mode(EditorMode::Insert)
+  }
+
+  // pushes the clipboard register out to the system clipboard after it was written
+  fn sync_clipboard(&mut self, register: Option<char>) {
+    if let Some(name) = register.filter(|name| Registers::is_clipboard(*name)) {
+      if let Some(content) = self.registers.get(name) {
+        self.clipboard.copy(&content.to_text())
+      }
+    }
+  }
+
+  // inserts text delivered by a bracketed paste as-is, bypassing auto-indent
+  fn paste_text(&mut self, text: &str) {
+    if self.refuse_edit() {
+      return
+    }
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let (line, column) = self.cursor();
+    let mut end = (line, column);
+    self.edit_rows(line..(line + 1), |file| {
+      end = file.insert_text((line, column), &text);
+    });
+    self.jump_to(end.0, end.1)
+  }
+
+  // 'after' distinguishes p (after the cursor / below the line) from P
+  fn put(&mut self, register: Option<char>, count: usize, after: bool) {
+    if register.is_some_and(Registers::is_clipboard) {
+      if let Some(text) = self.clipboard.paste() {
+        self.registers.set_clipboard(text)
+      }
+    }
+    let content = if let Some(content) = self.registers.get(register.unwrap_or('"')) {
+      content.clone()
+    } else {
+      return
+    };
+    let (line, column) = self.cursor();
+    match content {
+      RegisterContent::Linewise(lines) => {
+        let at = if after { line + 1 } else { line };
+        let rows: Vec<Row> = (0..count)
+          .flat_map(|_| lines.iter().map(|line| Row::from(line.as_str())))
+          .collect();
+        self.edit_rows(at..at, |file| file.insert_rows(at, rows));
+        self.jump_to(at, 0)
+      },
+      RegisterContent::Charwise(text) => {
+        let row_len = self.file.get_row(line).unwrap().len();
+        let at = if after { std::cmp::min(column + 1, row_len) } else { column };
+        let text = text.repeat(count);
+        let mut end = (line, at);
+        self.edit_rows(line..(line + 1), |file| {
+          end = file.insert_text((line, at), &text);
+        });
+        self.jump_to(end.0, end.1.saturating_sub(1))
+      }
+    }
+  }
+
+  // where the cursor lands on match 'idx' once 'offset' is applied
+  fn match_target(&self, idx: usize, offset: Option<SearchOffset>) -> (usize, usize) {
+    let (row, columns) = &self.search_matches[idx];
+    let start = columns.start;
+    let row_len = self.file.rows[*row].len() as i64;
+    let clamp = |column: i64| std::cmp::max(0, std::cmp::min(column, row_len)) as usize;
+    match offset {
+      None => (*row, start),
+      Some(SearchOffset::Line(lines)) => {
+        let target = std::cmp::max(0, *row as i64 + lines) as usize;
+        (std::cmp::min(target, self.file.len() - 1), 0)
+      },
+      Some(SearchOffset::Start(offset_columns)) => (*row, clamp(start as i64 + offset_columns)),
+      Some(SearchOffset::End(offset_columns)) => {
+        (*row, clamp(std::cmp::max(columns.end, start + 1) as i64 - 1 + offset_columns))
+      }
+    }
+  }
+
+  // the match after (or before) 'from' in document order, wrapping around
+  // the document's end; also returns whether it wrapped
+  fn next_match(&self, from: (usize, usize), backward: bool, offset: Option<SearchOffset>) -> Option<(usize, bool)> {
+    let targets: Vec<(usize, usize)> = (0..self.search_matches.len())
+      .map(|idx| self.match_target(idx, offset))
+      .collect();
+    let found = if backward {
+      targets.iter().rposition(|target| *target < from)
+    } else {
+      targets.iter().position(|target| *target > from)
+    };
+    match found {
+      Some(idx) => Some((idx, false)),
+      None if targets.is_empty() => None,
+      None => Some((if backward { targets.len() - 1 } else { 0 }, true))
+    }
+  }
+
+  // finds every match of 'pattern', false (with the reason in the status bar) if it is invalid
+  fn find_matches(&mut self, pattern: &str) -> bool {
+    self.search_matches.clear();
+    self.matches_of = None;
+    if pattern.is_empty() {
+      return true
+    }
+    match search::compile(pattern, &self.search_options) {
+      Ok(regex) => {
+        self.search_matches = self.file.search_for(&regex);
+        true
+      },
+      Err(why) => {
+        self.status_bar.message = why;
+        false
+      }
+    }
+  }
+
+  // ]d and [d, the count-th diagnostic after or before the cursor, wrapping
+  // around the document; its message shows on the status bar once there
+  fn next_diagnostic(&mut self, backward: bool, count: usize) {
+    let mut target = self.cursor();
+    for _ in 0..count {
+      match self.file.diagnostics.next(target, backward) {
+        Some(start) => target = start,
+        None => {
+          self.status_bar.message = String::from("no diagnostics");
+          return
+        }
+      }
+    }
+    self.record_jump();
+    self.jump_to(target.0, target.1)
+  }
+
+  // repeats the last search 'count' times, 'reverse' turns its direction around
+  fn search_next(&mut self, reverse: bool, count: usize) {
+    let search = match self.last_search.clone() {
+      Some(search) => search,
+      None => {
+        self.status_bar.message = String::from("no previous pattern");
+        return
+      }
+    };
+    if !self.find_matches(&search.pattern) {
+      return
+    }
+    self.highlight_search = true;
+    let backward = search.backward != reverse;
+    let mut target = self.cursor();
+    let mut current = None;
+    let mut wrapped = false;
+    for _ in 0..count {
+      match self.next_match(target, backward, search.offset) {
+        Some((idx, wrap)) => {
+          target = self.match_target(idx, search.offset);
+          current = Some(idx);
+          wrapped |= wrap
+        },
+        None => break
+      }
+    }
+    let current = match current {
+      Some(current) => current,
+      None => {
+        self.status_bar.message = format!("pattern not found: {}", search.pattern);
+        return
+      }
+    };
+    self.record_jump();
+    self.jump_to(target.0, target.1);
+    let counter = format!("[{}/{}]", current + 1, self.search_matches.len());
+    self.status_bar.message = match (wrapped, backward) {
+      (true, false) => format!("search hit BOTTOM, continuing at TOP {}", counter),
+      (true, true) => format!("search hit TOP, continuing at BOTTOM {}", counter),
+      _ => counter
+    }
+  }
+
+  // the keyword at or after the cursor on its row, for * and #
+  fn word_under_cursor(&self) -> Option<String> {
+    let (row, column) = self.cursor();
+    let graphemes = motion::graphemes(&self.file, row);
+    let is_word = |idx: &usize| motion::class_of(graphemes[*idx]) == CharClass::Word;
+    let start = (column..graphemes.len()).find(is_word)?;
+    let first = (0..start).rev().take_while(is_word).last().unwrap_or(start);
+    let end = (start..graphemes.len()).take_while(is_word).last()? + 1;
+    Some(graphemes[first..end].concat())
+  }
+
+  // highlights and moves to matches while a search pattern is being typed,
+  // going back to where it started once the pattern is gone
+  fn incremental_search(&mut self) {
+    let cmd = self.status_bar.cmd.strip_prefix(':').unwrap_or(&self.status_bar.cmd);
+    let search = match search::parse(cmd) {
+      Some(search) if self.mode == EditorMode::Command => search,
+      _ => {
+        if let Some((row, column)) = self.search_origin.take() {
+          self.search_matches.clear();
+          self.matches_of = None;
+          self.jump_to(row, column)
+        }
+        return
+      }
+    };
+    let (row, column) = *self.search_origin.get_or_insert(self.cursor());
+    self.jump_to(row, column);
+    self.find_matches(&search.pattern);
+    if let Some((idx, _)) = self.next_match((row, column), search.backward, None) {
+      let (row, column) = self.match_target(idx, None);
+      self.jump_to(row, column)
+    }
+  }
+
+  fn render(&mut self) {
+    self.set_buffer();
+    let _ = execute!(
+      stdout(),
+      Hide,
+      SavePosition,
+      MoveTo(0, 0),
+    );
+    let num_rows = self.file.rows.len();
+    let buffer = self.buffer as usize;
+    if self.mode != EditorMode::Command {
+      self.file.highlight();
+      match &self.last_search {
+        Some(search) if self.highlight_search => {
+          let searched = (search.pattern.clone(), self.search_options, self.file.version());
+          if self.matches_of.as_ref() != Some(&searched) {
+            self.find_matches(&searched.0);
+            self.matches_of = Some(searched)
+          }
+        },
+        _ => {
+          self.search_matches.clear();
+          self.matches_of = None
+        }
+      }
+    }
+    let bracket = match self.mode {
+      EditorMode::Normal | EditorMode::Insert => motion::matching_bracket(&self.file, self.cursor()),
+      _ => None
+    };
+    // the field being typed in, wherever its number appears
+    let snippet_fields: Vec<(usize, Range<usize>)> = match &self.snippet {
+      Some(session) => session.fields
+        .iter()
+        .filter(|field| field.number == session.current && field.len > 0)
+        .map(|field| (field.row, field.column..(field.column + field.len)))
+        .collect(),
+      None => Vec::new()
+    };
+    for terminal_row_no in self.view_frame.0..(self.view_frame.1 - 1) {
+      if terminal_row_no < num_rows {
+        self.clear_row();
+        let used = buffer - (terminal_row_no + 1).to_string().chars().count();
+        let marked: Vec<Range<usize>> = self.marked
+          .iter()
+          .chain(self.search_matches.iter())
+          .chain(snippet_fields.iter())
+          .filter(|(row, _)| *row == terminal_row_no)
+          .map(|(_, range)| range.clone())
+          .collect();
+        let bracket = bracket.filter(|(row, _)| *row == terminal_row_no).map(|(_, column)| column);
+        self.write_row(terminal_row_no + 1, used, self.file.rows.get(terminal_row_no).unwrap(), &marked, bracket);
+      } else {
+        self.write_empty_line();
+      }
+    }
+    self.status_bar.diagnostic = match self.file.diagnostics.at(self.cursor()) {
+      Some(diagnostic) => format!("{}: {}", diagnostic.severity.label(), diagnostic.message.lines().next().unwrap_or("")),
+      None => String::new()
+    };
+    if self.search_origin.is_some() && !self.search_matches.is_empty() {
+      // while a pattern is typed the cursor sits on the match being counted
+      let cursor = self.cursor();
+      let current = (0..self.search_matches.len())
+        .filter(|idx| self.match_target(*idx, None) <= cursor)
+        .count();
+      self.status_bar.render(current, self.search_matches.len())
+    } else {
+      self.status_bar.render(self.view_frame.0 + self.position.0 as usize + 1, self.file.len())
+    }
+    self.render_completion();
+    match self.mode {
+      EditorMode::Command => (),
+      EditorMode::Picker => self.render_picker(),
+      _ => {
+        let _ = execute!(stdout(), RestorePosition, Show);
+        let (column, row) = position().unwrap();
+        if column == 0 {
+          self.position.1 = self.buffer + 1;
+          let _ = execute!(
+            stdout(),
+            MoveTo(self.position.1, row)
+          );
+        }
+      }
+    }
+    let _ = stdout().flush();
+  }
+}
+
+impl Drop for Editor {
+  fn drop(&mut self) {
+    let _ = self.terminal.clear();
+    let _ = recent::save(&self.old_files);
+    if self.altered && self._quit {
       if let Err(why) = self.file.save() {
         eprintln!("{}", why)
       }
     }
+    if self._quit {
+      for (name, document) in &self.modified {
+        if let Err(why) = document.save() {
+          eprintln!("{}: {}", name, why)
+        }
+      }
+    }
     let _ = execute!(
       stdout(),
+      DisableBracketedPaste,
       ResetColor,
       LeaveAlternateScreen,
       ResetColor,
@@ -891,31 +3706,26 @@ impl Drop for Editor {
   }
 }
 
+// how many whitespace characters 'row' starts with
+fn indentation(row: &Row) -> usize {
+  row.content().chars().take_while(|ch| ch.is_whitespace()).count()
+}
+
+// a "row:column" pair as kept in a picker item
+fn parse_position(text: &str) -> Option<(usize, usize)> {
+  let (row, column) = text.split_once(':')?;
+  Some((row.parse().ok()?, column.parse().ok()?))
+}
+
 fn numeric_modifer(commands: &mut String) -> u32 {
   let mut modifier = String::new();
   while let Some(character) = commands.pop() {
-    if let Some(_) = character.to_digit(10) {
+    if character.is_ascii_digit() {
       modifier.push(character)
     } else {
       commands.push(character);
       break
     }
   }
-  if let Ok(modifier) = modifier.parse::<u32>() {
-    modifier
-  } else {
-    1
-  }
-}
-
-fn word_modifier(commands: &mut String) -> Option<String> {
-  let mut modifier = String::new();
-  while let Some(character) = commands.pop() {
-    modifier.push(character)
-  }
-  if modifier.len() > 0 {
-    Some(modifier)
-  } else {
-    None
-  }
+  modifier.parse::<u32>().unwrap_or(1)
 }
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExplorerEntry {
+  pub name: String,
+  pub is_dir: bool,
+  // a git status letter, M modified, A added, D deleted, R renamed,
+  // U conflicted or ? untracked; for a directory, that of what it contains
+  pub status: Option<char>
+}
+
+// the listing of one directory, shown as a buffer with "../" in its first row
+#[derive(Debug, Clone)]
+pub struct Explorer {
+  pub dir: String,
+  pub entries: Vec<ExplorerEntry>
+}
+
+// resolves . and .. in a relative path without touching the file system,
+// so names stay the way they were typed rather than becoming absolute
+pub fn normalize(path: &Path) -> String {
+  let mut parts: Vec<Component> = Vec::new();
+  for component in path.components() {
+    match component {
+      Component::CurDir => (),
+      Component::ParentDir => match parts.last() {
+        Some(Component::Normal(_)) => {
+          parts.pop();
+        },
+        Some(Component::RootDir) => (),
+        _ => parts.push(component)
+      },
+      component => parts.push(component)
+    }
+  }
+  let path: PathBuf = parts.iter().collect();
+  if path.as_os_str().is_empty() {
+    String::from(".")
+  } else {
+    path.to_string_lossy().to_string()
+  }
+}
+
+// the status letter for each changed path of the repository 'dir' is in, by
+// absolute path; empty outside of a repository or without git
+fn git_status(dir: &Path) -> HashMap<PathBuf, char> {
+  let mut statuses = HashMap::new();
+  let root = match Command::new("git").arg("rev-parse").arg("--show-toplevel").current_dir(dir).output() {
+    Ok(output) if output.status.success() => PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()),
+    _ => return statuses
+  };
+  let output = match Command::new("git").args(["status", "--porcelain", "-z"]).current_dir(dir).output() {
+    Ok(output) if output.status.success() => output.stdout,
+    _ => return statuses
+  };
+  let output = String::from_utf8_lossy(&output);
+  let mut fields = output.split('\0');
+  while let Some(field) = fields.next() {
+    let (code, path) = match (field.get(..2), field.get(3..)) {
+      (Some(code), Some(path)) => (code, path),
+      _ => continue
+    };
+    let mut code = code.chars();
+    let (staged, unstaged) = (code.next().unwrap_or(' '), code.next().unwrap_or(' '));
+    // renames and copies are followed by the name they came from
+    if matches!(staged, 'R' | 'C') {
+      fields.next();
+    }
+    let status = if staged == 'U' || unstaged == 'U' {
+      'U'
+    } else if unstaged != ' ' {
+      unstaged
+    } else {
+      staged
+    };
+    statuses.insert(root.join(path.trim_end_matches('/')), status);
+  }
+  statuses
+}
+
+impl Explorer {
+  pub fn read(dir: &str) -> std::io::Result<Self> {
+    let dir = normalize(Path::new(dir));
+    let statuses = git_status(Path::new(&dir));
+    let absolute = Path::new(&dir).canonicalize()?;
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+      let entry = entry?;
+      let name = entry.file_name().to_string_lossy().to_string();
+      let is_dir = entry.path().is_dir();
+      let path = absolute.join(&name);
+      let status = match statuses.get(&path) {
+        Some(status) => Some(*status),
+        None if is_dir => {
+          let mut inside = statuses.iter().filter(|(changed, _)| changed.starts_with(&path)).map(|(_, status)| *status);
+          inside.next().map(|first| if inside.all(|status| status == first) { first } else { 'M' })
+        },
+        None => None
+      };
+      entries.push(ExplorerEntry { name, is_dir, status })
+    }
+    entries.sort_by(|first, second| second.is_dir.cmp(&first.is_dir).then(first.name.cmp(&second.name)));
+    Ok(Self { dir, entries })
+  }
+
+  pub fn lines(&self) -> Vec<String> {
+    std::iter::once(String::from("  ../"))
+      .chain(self.entries.iter().map(|entry| format!("{} {}{}",
+        entry.status.unwrap_or(' '),
+        entry.name,
+        if entry.is_dir { "/" } else { "" })))
+      .collect()
+  }
+
+  // the entry shown on 'row', None for the parent directory row
+  pub fn entry(&self, row: usize) -> Option<&ExplorerEntry> {
+    self.entries.get(row.checked_sub(1)?)
+  }
+
+  pub fn row_of(&self, name: &str) -> Option<usize> {
+    self.entries.iter().position(|entry| entry.name == name).map(|idx| idx + 1)
+  }
+
+  // the path of 'name' inside the listed directory
+  pub fn path(&self, name: &str) -> String {
+    normalize(&Path::new(&self.dir).join(name))
+  }
+
+  pub fn parent(&self) -> String {
+    self.path("..")
+  }
+}
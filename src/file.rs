@@ -1,9 +1,12 @@
 use std::io::Write;
-use std::fs::{read_to_string, OpenOptions};
+use std::ops::Range;
+use std::fs::{read_to_string, remove_file, rename, set_permissions, symlink_metadata, Metadata, OpenOptions};
+use std::path::Path;
 use unicode_segmentation::UnicodeSegmentation;
 use regex::Regex;
 use dirs::home_dir;
 use crate::highlighting::*;
+use crate::diagnostics::Diagnostics;
 
 // newline position descriptor
 pub enum NLPositionDescriptor {
@@ -33,7 +36,7 @@ pub struct Row {
 
 impl AsRef<Row> for Row {
   fn as_ref(&self) -> &Self {
-    &self
+    self
   }
 }
 
@@ -46,16 +49,26 @@ impl Row {
     self.len
   }
 
-  pub fn search_for(&mut self, row_id: usize, expr: &String) -> (usize, Vec<(usize, usize)>) {
-    let reg_expr = if let Ok(reg_expr) = Regex::new(expr) {
-      reg_expr
-    } else {
-      return (0, Vec::new())
-    };
-    let search_results: Vec<(usize, usize)> = reg_expr.find_iter(&self.content)
-      .filter_map(|regex_match| Some((row_id, regex_match.start())))
+  // matches as column ranges, widened to whole graphemes where a match splits one
+  pub fn search_for(&self, row_id: usize, expr: &Regex) -> Vec<(usize, Range<usize>)> {
+    let boundaries: Vec<(usize, usize)> = self.content
+      .grapheme_indices(true)
+      .map(|(idx, grapheme)| (idx, idx + grapheme.len()))
       .collect();
-    (search_results.len(), search_results)
+    // matches come in order, so the graphemes before each one are counted on
+    // from where the previous match left off
+    let (mut start, mut end) = (0, 0);
+    expr.find_iter(&self.content)
+      .map(|regex_match| {
+        while start < boundaries.len() && boundaries[start].1 <= regex_match.start() {
+          start += 1
+        }
+        while end < boundaries.len() && boundaries[end].0 < regex_match.end() {
+          end += 1
+        }
+        (row_id, start..std::cmp::max(start, end))
+      })
+      .collect()
   }
 
   pub fn insert(&mut self, descrip: IPositionDescriptor) {
@@ -89,6 +102,7 @@ impl Row {
         let len = self.content.graphemes(true).count();
         let content = self.content.clone();
         self.content = old;
+        self.len = 0;
         Row {
           content,
           len,
@@ -142,6 +156,39 @@ impl Row {
     self.len -= 1;
     self.content.pop()
   }
+
+  // byte offset of the grapheme at 'column', clamped to the end of the row
+  pub fn byte_index(&self, column: usize) -> usize {
+    self.content
+      .grapheme_indices(true)
+      .nth(column)
+      .map(|(idx, _)| idx)
+      .unwrap_or_else(|| self.content.len())
+  }
+
+  pub fn slice(&self, columns: Range<usize>) -> &str {
+    &self.content[self.byte_index(columns.start)..self.byte_index(columns.end)]
+  }
+
+  pub fn insert_str(&mut self, column: usize, string: &str) {
+    let idx = self.byte_index(column);
+    self.content.insert_str(idx, string);
+    self.len = self.content.graphemes(true).count()
+  }
+
+  pub fn remove(&mut self, columns: Range<usize>) -> String {
+    let range = self.byte_index(columns.start)..self.byte_index(columns.end);
+    let removed = self.content.drain(range).collect();
+    self.len = self.content.graphemes(true).count();
+    removed
+  }
+
+  pub fn split_off(&mut self, column: usize) -> Row {
+    let idx = self.byte_index(column);
+    let rest = self.content.split_off(idx);
+    self.len = self.content.graphemes(true).count();
+    Row::from(rest.as_str())
+  }
 }
 
 impl From<&str> for Row {
@@ -159,6 +206,36 @@ pub struct Document {
   pub rows: Vec<Row>,
   pub syntax_file: Option<JsonValue>,
   pub highlighted_rows: Option<Vec<Vec<Parsed>>>,
+  // rows replaced since last taken: each range became that many rows
+  row_edits: Vec<(Range<usize>, usize)>,
+  // generated by the editor (such as a results list) and never written out
+  pub scratch: bool,
+  // reported by language servers and builds, shown in the gutter and underlined
+  pub diagnostics: Diagnostics,
+  // the file's lines end in \r\n, which they are written back with
+  crlf: bool,
+  // counts the changes to the rows, so what is worked out from them can tell
+  // when it is out of date
+  version: u64,
+}
+
+fn write_file(path: &Path, content: &str) -> Result<(), std::io::Error> {
+  let mut file = OpenOptions::new().write(true).truncate(true).create(true).open(path)?;
+  file.write_all(content.as_bytes())?;
+  file.sync_all()
+}
+
+// whether 'copy' can stand in for the file 'original' describes: it has no
+// other names to keep pointing at it and the copy now has its owner
+#[cfg(unix)]
+fn takes_owner(copy: &Path, original: &Metadata) -> bool {
+  use std::os::unix::fs::{chown, MetadataExt};
+  original.nlink() == 1 && chown(copy, Some(original.uid()), Some(original.gid())).is_ok()
+}
+
+#[cfg(not(unix))]
+fn takes_owner(_copy: &Path, _original: &Metadata) -> bool {
+  true
 }
 
 impl Document {
@@ -176,13 +253,17 @@ impl Document {
       rows,
       syntax_file,
       highlighted_rows,
+      row_edits: Vec::new(),
+      scratch: false,
+      diagnostics: Diagnostics::new(),
+      crlf: raw_content.find('\n').is_some_and(|end| raw_content[..end].ends_with('\r')),
+      version: 0,
     })
   }
 
   pub fn new(file_name: &str) -> Self {
     let file_name = String::from(file_name);
-    let mut rows = Vec::new();
-    rows.push(Row::from(""));
+    let rows = vec![Row::from("")];
     let syntax_file = Self::load_syntax_file(&file_name);
     let highlighted_rows = highlight(&file_name, &rows, &syntax_file);
     Self {
@@ -190,23 +271,33 @@ impl Document {
       rows,
       syntax_file,
       highlighted_rows,
+      row_edits: Vec::new(),
+      scratch: false,
+      diagnostics: Diagnostics::new(),
+      crlf: false,
+      version: 0,
+    }
+  }
+
+  pub fn scratch(name: &str, lines: &[String]) -> Self {
+    let mut document = Self::new(name);
+    if !lines.is_empty() {
+      document.rows = lines.iter().map(|line| Row::from(line.as_str())).collect();
     }
+    document.scratch = true;
+    document
   }
 
-  fn load_syntax_file(file_name: &String) -> Option<JsonValue> {
+  fn load_syntax_file(file_name: &str) -> Option<JsonValue> {
     if let Ok(file_contents) = read_to_string(
       home_dir()
       .unwrap()
-      .join(if let Some(extension) = file_name.split('.').collect::<Vec<&str>>().last() {
-        match *extension {
-          "rs" => ".editrc/syntax/rust.json",
-          "py" => ".editrc/syntax/python.json",
-          "c" |
-          "cc" |
-          "h" => ".editrc/syntax/c.json",
-          _ => ""
-        }
-      } else { "" })) {
+      .join(match Language::from_file_name(file_name) {
+        Some(Language::Rust) => ".editrc/syntax/rust.json",
+        Some(Language::Python) => ".editrc/syntax/python.json",
+        Some(Language::C) => ".editrc/syntax/c.json",
+        None => ""
+      })) {
       if let Ok(result) = json::parse(&file_contents) {
         if !result["highlight"].as_bool().unwrap() {
           None
@@ -233,6 +324,31 @@ impl Document {
     &self.highlighted_rows
   }
 
+  pub fn language(&self) -> Option<Language> {
+    Language::from_file_name(&self.file_name)
+  }
+
+  pub fn tokens(&self) -> Option<Vec<Vec<Token>>> {
+    Some(self.language()?.tokenize(&self.rows))
+  }
+
+  // the nearest row above 'row' that starts at the margin, which begins a
+  // top-level item that nothing before it changes the reading of; comments
+  // and C's preprocessor lines sit at the margin inside blocks too, so they
+  // don't count
+  pub fn item_start(&self, row: usize) -> usize {
+    self.rows[..row]
+      .iter()
+      .rposition(|row| row.content().starts_with(|ch: char| !ch.is_whitespace() && !matches!(ch, '#' | '/' | '*')))
+      .unwrap_or(0)
+  }
+
+  // the tokens of 'row', lexed from the start of the item it is in rather
+  // than from the top of the document
+  pub fn row_tokens(&self, row: usize) -> Option<Vec<Token>> {
+    self.language()?.tokenize(&self.rows[self.item_start(row)..=row]).pop()
+  }
+
   pub fn set_name(&mut self, name: &str) {
     self.file_name = String::from(name);
     self.load_syntax()
@@ -242,13 +358,12 @@ impl Document {
     self.rows.get(index)
   }
 
-  pub fn clear_row(&mut self, index: usize) {
-    let row = self.rows.get_mut(index).unwrap();
-    row.content.clear();
-    row.len = 0
+  pub fn version(&self) -> u64 {
+    self.version
   }
 
   pub fn get_row_mut(&mut self, index: usize) -> Result<&mut Row, ()> {
+    self.version += 1;
     if let Some(row) = self.rows.get_mut(index) {
       Ok(row)
     } else {
@@ -264,18 +379,50 @@ impl Document {
     let mut stringified = String::new();
     for row in &self.rows {
       stringified.push_str(row.content());
-      stringified.push('\n')
+      stringified.push_str(if self.crlf { "\r\n" } else { "\n" })
     }
     stringified
   }
 
+  // writes to a temporary file beside the real one and renames it over the
+  // original, so a failed write never leaves a truncated file behind; links
+  // and files that the copy could not take the owner of are written in place
   pub fn save(&self) -> Result<usize, std::io::Error> {
-    let mut file = OpenOptions::new().write(true).truncate(true).create(true).open(self.file_name.as_str())?;
-    file.write(self.to_str().as_bytes())
+    if self.scratch {
+      return Ok(0)
+    }
+    if self.file_name.is_empty() {
+      return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no file name"))
+    }
+    let path = Path::new(&self.file_name);
+    let content = self.to_str();
+    let metadata = match symlink_metadata(path) {
+      Ok(metadata) if metadata.is_file() => metadata,
+      _ => {
+        write_file(path, &content)?;
+        return Ok(content.len())
+      }
+    };
+    let temporary = path.with_file_name(format!(
+      ".{}.silver-{}.tmp",
+      path.file_name().unwrap_or_default().to_string_lossy(),
+      std::process::id()
+    ));
+    let replaced = write_file(&temporary, &content).is_ok()
+      && set_permissions(&temporary, metadata.permissions()).is_ok()
+      && takes_owner(&temporary, &metadata)
+      && rename(&temporary, path).is_ok();
+    if !replaced {
+      let _ = remove_file(&temporary);
+      write_file(path, &content)?;
+    }
+    Ok(content.len())
   }
 
   pub fn insert_row(&mut self, row_no: usize, row: Row) {
-    self.rows.insert(row_no, row)
+    self.version += 1;
+    self.rows.insert(row_no, row);
+    self.row_edits.push((row_no..row_no, 1))
   }
 
   pub fn handle_delete(&mut self, descrip: DPositionDescriptor) -> Option<usize> {
@@ -289,6 +436,8 @@ impl Document {
           return None
         }
         let row = self.rows.remove(row_no);
+        self.version += 1;
+        self.row_edits.push((row_no..(row_no + 1), 0));
         let prev_row = self.rows.get_mut(row_no - 1).unwrap();
         let prev_len = prev_row.len();
         prev_row.append(&row);
@@ -301,39 +450,105 @@ impl Document {
     }
   }
 
-  pub fn highlight(&mut self) {
-    self.highlighted_rows = highlight(&self.file_name, &self.rows, &self.syntax_file);
+  pub fn insert_rows(&mut self, row_no: usize, rows: Vec<Row>) {
+    self.splice_rows(row_no..row_no, rows)
+  }
+
+  // removes whole rows, always leaving at least one (empty) row behind
+  pub fn remove_rows(&mut self, range: Range<usize>) -> Vec<Row> {
+    let removed = self.rows[range.clone()].to_vec();
+    self.splice_rows(range, Vec::new());
+    removed
+  }
+
+  pub fn splice_rows(&mut self, range: Range<usize>, rows: Vec<Row>) {
+    self.version += 1;
+    self.row_edits.push((range.clone(), rows.len()));
+    self.rows.splice(range, rows);
+    if self.rows.is_empty() {
+      self.rows.push(Row::from(""));
+      self.row_edits.push((0..0, 1))
+    }
   }
 
-  pub fn replace(&mut self, index: usize, new_row: Row) {
-    self.rows[index] = new_row;
+  // the row replacements made since this was last called
+  pub fn take_row_edits(&mut self) -> Vec<(Range<usize>, usize)> {
+    std::mem::take(&mut self.row_edits)
+  }
+
+  // text between two (row, column) positions, end exclusive
+  pub fn text_range(&self, start: (usize, usize), end: (usize, usize)) -> String {
+    if start.0 == end.0 {
+      return self.rows[start.0].slice(start.1..end.1).to_string()
+    }
+    let mut text = String::from(self.rows[start.0].slice(start.1..self.rows[start.0].len()));
+    for row in &self.rows[(start.0 + 1)..end.0] {
+      text.push('\n');
+      text.push_str(row.content())
+    }
+    text.push('\n');
+    text.push_str(self.rows[end.0].slice(0..end.1));
+    text
+  }
+
+  // inserts possibly multi-line text, returning the position right after it
+  pub fn insert_text(&mut self, at: (usize, usize), text: &str) -> (usize, usize) {
+    let mut lines = text.split('\n');
+    let first = lines.next().unwrap_or("");
+    let rest: Vec<&str> = lines.collect();
+    let row = self.get_row_mut(at.0).unwrap();
+    if rest.is_empty() {
+      row.insert_str(at.1, first);
+      return (at.0, at.1 + Row::from(first).len())
+    }
+    let tail = row.split_off(at.1);
+    row.insert_str(at.1, first);
+    let mut new_rows: Vec<Row> = rest.iter().map(|line| Row::from(*line)).collect();
+    let last = new_rows.last_mut().unwrap();
+    let end = (at.0 + rest.len(), last.len());
+    last.append(&tail);
+    self.insert_rows(at.0 + 1, new_rows);
+    end
+  }
+
+  // removes the text between two (row, column) positions, end exclusive
+  pub fn delete_text(&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+    let removed = self.text_range(start, end);
+    self.version += 1;
+    if start.0 == end.0 {
+      self.rows[start.0].remove(start.1..end.1);
+    } else {
+      let tail = self.rows[end.0].split_off(end.1);
+      let row_len = self.rows[start.0].len();
+      self.rows[start.0].remove(start.1..row_len);
+      self.rows[start.0].append(&tail);
+      self.rows.drain((start.0 + 1)..=end.0);
+      self.row_edits.push(((start.0 + 1)..(end.0 + 1), 0));
+    }
+    removed
+  }
+
+  pub fn highlight(&mut self) {
+    self.highlighted_rows = highlight(&self.file_name, &self.rows, &self.syntax_file);
   }
 
   // return type is as follows:
-  // (num_results, Vec<(row_idx, match_idx_start)>)
-  pub fn search_for(&mut self, expr: &String) -> (usize, Vec<(usize, usize)>) {
-    let mut counter = 0;
+  // Vec<(row_idx, match_column_range)>
+  pub fn search_for(&self, expr: &Regex) -> Vec<(usize, Range<usize>)> {
     let mut search_results = Vec::new();
-    for (row_id, row) in self.rows.iter_mut().enumerate() {
-      let (num_results, mut results) = row.search_for(row_id, expr);
-      counter += num_results;
-      search_results.append(&mut results)
+    for (row_id, row) in self.rows.iter().enumerate() {
+      search_results.append(&mut row.search_for(row_id, expr))
     }
-    (counter, search_results)
+    search_results
   }
 }
 
 // "static" helper functions
 
-pub fn highlight(file_name: &str, rows: &Vec<Row>, syntax_file: &Option<JsonValue>) -> Option<Vec<Vec<Parsed>>> {
-  if let Some(extension) = file_name.split('.').collect::<Vec<&str>>().last() {
-    match *extension {
-      "rs" => RustLexer::lex(&rows, syntax_file.as_ref()).parse(),
-      "py" => PythonLexer::lex(&rows, syntax_file.as_ref()).parse(),
-      "c" |
-      "cc" |
-      "h" => CLexer::lex(&rows, syntax_file.as_ref()).parse(),
-      _ => None
-    }
-  } else { None }
+pub fn highlight(file_name: &str, rows: &[Row], syntax_file: &Option<JsonValue>) -> Option<Vec<Vec<Parsed>>> {
+  match Language::from_file_name(file_name)? {
+    Language::Rust => RustLexer::lex(rows, syntax_file.as_ref()).parse(),
+    Language::Python => PythonLexer::lex(rows, syntax_file.as_ref()).parse(),
+    Language::C => CLexer::lex(rows, syntax_file.as_ref()).parse()
+  }
 }
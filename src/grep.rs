@@ -0,0 +1,37 @@
+use std::fs::read_to_string;
+use ignore::WalkBuilder;
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::quickfix::QuickfixEntry;
+
+// every line under 'root' that matches 'regex', leaving out what .gitignore
+// (and .ignore) files exclude, hidden files and anything that isn't UTF-8
+pub fn grep(regex: &Regex, root: &str) -> Vec<QuickfixEntry> {
+  let mut entries = Vec::new();
+  let walk = WalkBuilder::new(root)
+    .require_git(false)
+    .sort_by_file_name(|first, second| first.cmp(second))
+    .build();
+  for dir_entry in walk.flatten() {
+    if !dir_entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+      continue
+    }
+    let path = dir_entry.path();
+    let contents = match read_to_string(path) {
+      Ok(contents) => contents,
+      Err(_) => continue
+    };
+    let file = path.strip_prefix("./").unwrap_or(path).to_string_lossy().to_string();
+    for (row, line) in contents.lines().enumerate() {
+      if let Some(found) = regex.find(line) {
+        entries.push(QuickfixEntry {
+          file: file.clone(),
+          row,
+          column: line[..found.start()].graphemes(true).count(),
+          text: line.trim().to_string()
+        })
+      }
+    }
+  }
+  entries
+}
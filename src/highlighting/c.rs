@@ -1,7 +1,7 @@
 use crate::highlighting::{
   Lexer, Parsed, Row, Color,
   get_color, Attribute, Logos, LogosLexer,
-  JsonValue
+  JsonValue, Lexed, Token, TokenKind
 };
 
 fn trim_function(token: &mut LogosLexer<CToken>) -> String {
@@ -48,7 +48,7 @@ enum CToken {
   #[token("default")]
   Keyword,
 
-  #[regex(r"u?int_(8|16|32|64)_t")]
+  #[regex(r"u?int(8|16|32|64)_t")]
   #[regex(r"<(([a-zA-Z0-9]|-|_)+/?)+\.h>")]
   #[token("char *")]
   #[token("int")]
@@ -77,10 +77,62 @@ enum CToken {
   DontCare
 }
 
+pub const KEYWORDS: &[&str] = &[
+  "include", "define", "ifndef", "endif", "if", "else", "while", "do", "for",
+  "enum", "struct", "break", "true", "false", "continue", "return", "switch",
+  "case", "const", "typedef", "union", "default",
+  "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t",
+  "uint64_t", "int", "unsigned", "NULL", "bool", "short", "long", "void", "char"
+];
+
+fn lex_rows(rows: &[Row]) -> Lexed<CToken> {
+  let mut lex = Vec::new();
+  let mut multiline_flag = false;
+  for row in rows {
+    let mut row_lex = Vec::new();
+    let lexed = CToken::lexer(row.content()).spanned();
+    for token_range in lexed {
+      match token_range.0 {
+        CToken::MultiLineCommentStart => {
+          multiline_flag = true;
+          row_lex.push(token_range)
+        },
+        CToken::MultiLineCommentEnd => {
+          multiline_flag = false;
+          row_lex.push(token_range)
+        }
+        CToken::Function(name) => {
+          if multiline_flag {
+            row_lex.push((CToken::Comment, token_range.1));
+            continue
+          }
+          row_lex.push((CToken::Function(name), std::ops::Range {
+            start: token_range.1.start,
+            end: token_range.1.end - 1
+          }));
+          row_lex.push((CToken::DontCare, std::ops::Range {
+            start: token_range.1.end - 1,
+            end: token_range.1.end
+          }))
+        },
+        _ => {
+          if multiline_flag {
+            row_lex.push((CToken::Comment, token_range.1));
+            continue
+          }
+          row_lex.push(token_range)
+        }
+      }
+    }
+    lex.push(row_lex)
+  }
+  lex
+}
+
 pub struct CLexer<'a> {
   _syntax: Option<json::JsonValue>,
-  _lex: Option<Vec<Vec<(CToken, std::ops::Range<usize>)>>>,
-  _raw: Option<&'a Vec<Row>>
+  _lex: Option<Lexed<CToken>>,
+  _raw: Option<&'a [Row]>
 }
 
 impl<'a> Lexer<'a> for CLexer<'a> {
@@ -93,48 +145,9 @@ impl<'a> Lexer<'a> for CLexer<'a> {
     }
   }
 
-  fn lex(rows: &'a Vec<Row>, syntax_file: Option<&JsonValue>) -> Self {
+  fn lex(rows: &'a [Row], syntax_file: Option<&JsonValue>) -> Self {
     if let Some(syntax) = syntax_file {
-      let mut lex = Vec::new();
-      let mut multiline_flag = false;
-      for row in rows {
-        let mut row_lex = Vec::new();
-        let lexed = CToken::lexer(row.content()).spanned();
-        for token_range in lexed {
-          match token_range.0 {
-            CToken::MultiLineCommentStart => {
-              multiline_flag = true;
-              row_lex.push(token_range)
-            },
-            CToken::MultiLineCommentEnd => {
-              multiline_flag = false;
-              row_lex.push(token_range)
-            }
-            CToken::Function(name) => {
-              if multiline_flag {
-                row_lex.push((CToken::Comment, token_range.1));
-                continue
-              }
-              row_lex.push((CToken::Function(name), std::ops::Range {
-                start: token_range.1.start,
-                end: token_range.1.end - 1
-              }));
-              row_lex.push((CToken::DontCare, std::ops::Range {
-                start: token_range.1.end - 1,
-                end: token_range.1.end
-              }))
-            },
-            _ => {
-              if multiline_flag {
-                row_lex.push((CToken::Comment, token_range.1));
-                continue
-              }
-              row_lex.push(token_range)
-            }
-          }
-        }
-        lex.push(row_lex)
-      }
+      let lex = lex_rows(rows);
       Self {
         _lex: Some(lex),
         _syntax: Some(syntax.clone()),
@@ -147,17 +160,14 @@ impl<'a> Lexer<'a> for CLexer<'a> {
 
   // TODO: use syntax/rust.json to encode tokens & respective colors
   fn parse(&self) -> Option<Vec<Vec<Parsed>>> {
-    if self._lex == None {
-      return None
-    }
-    let lexed = self._lex.as_ref().unwrap();
+    let lexed = self._lex.as_ref()?;
     let mut parsed_file = Vec::new();
-    let mut raw_content_iter = self._raw.unwrap().into_iter();
+    let mut raw_content_iter = self._raw.unwrap().iter();
     for row in lexed {
       let raw_row = raw_content_iter.next().unwrap();
       let mut parsed_row = Vec::new();
       for (token, range) in row {
-        let original = String::from(raw_row.content()[range.clone()].to_string());
+        let original = raw_row.content()[range.clone()].to_string();
         parsed_row.push(Parsed {
           color: match_color(token, self._syntax.as_ref().unwrap()),
           range: range.clone(),
@@ -169,6 +179,34 @@ impl<'a> Lexer<'a> for CLexer<'a> {
     }
     Some(parsed_file)
   }
+
+  fn tokenize(rows: &[Row]) -> Vec<Vec<Token>> {
+    lex_rows(rows)
+      .into_iter()
+      .map(|row| row
+        .into_iter()
+        .map(|(token, range)| Token {
+          kind: token_kind(&token),
+          range
+        })
+        .collect())
+      .collect()
+  }
+}
+
+fn token_kind(token: &CToken) -> TokenKind {
+  match token {
+    CToken::Keyword => TokenKind::Keyword,
+    CToken::Type => TokenKind::Type,
+    CToken::String => TokenKind::String,
+    CToken::Char => TokenKind::Char,
+    CToken::Number => TokenKind::Number,
+    CToken::Function(_) => TokenKind::Function,
+    CToken::Comment |
+    CToken::MultiLineCommentStart |
+    CToken::MultiLineCommentEnd => TokenKind::Comment,
+    CToken::DontCare => TokenKind::Other
+  }
 }
 
 fn match_color(token: &CToken, syntax_rules: &JsonValue) -> Option<Color> {
@@ -13,6 +13,7 @@ use crate::file::Row;
 #[derive(Debug)]
 pub struct Parsed {
   original: String,
+  #[allow(dead_code)]
   range: std::ops::Range<usize>,
   color: Option<Color>,
   attr: Attribute
@@ -28,10 +29,81 @@ impl Parsed {
   }
 }
 
+pub type Lexed<T> = Vec<Vec<(T, std::ops::Range<usize>)>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+  Rust,
+  Python,
+  C
+}
+
+impl Language {
+  pub fn from_file_name(file_name: &str) -> Option<Self> {
+    match *file_name.split('.').collect::<Vec<&str>>().last()? {
+      "rs" => Some(Language::Rust),
+      "py" => Some(Language::Python),
+      "c" |
+      "cc" |
+      "h" => Some(Language::C),
+      _ => None
+    }
+  }
+
+  // what completion offers besides words, written out by hand from the
+  // Keyword and Type variants of each lexer's token enum; the test below
+  // keeps the two in step
+  pub fn keywords(&self) -> &'static [&'static str] {
+    match self {
+      Language::Rust => rust::KEYWORDS,
+      Language::Python => python::KEYWORDS,
+      Language::C => c::KEYWORDS
+    }
+  }
+
+  pub fn tokenize(&self, rows: &[Row]) -> Vec<Vec<Token>> {
+    match self {
+      Language::Rust => RustLexer::tokenize(rows),
+      Language::Python => PythonLexer::tokenize(rows),
+      Language::C => CLexer::tokenize(rows)
+    }
+  }
+}
+
+// language independent view of a lexer token, used by editing features
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+  Keyword,
+  Type,
+  String,
+  Char,
+  Number,
+  Function,
+  Comment,
+  Other
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+  pub kind: TokenKind,
+  // byte range within the row
+  pub range: std::ops::Range<usize>
+}
+
+// whether the byte at 'idx' of a row belongs to a string, char or comment token
+pub fn in_literal(tokens: &[Token], idx: usize) -> bool {
+  tokens.iter().any(|token| token.range.contains(&idx) && matches!(
+    token.kind,
+    TokenKind::String | TokenKind::Char | TokenKind::Comment
+  ))
+}
+
 pub trait Lexer<'a> {
   fn highlight_off() -> Self;
-  fn lex(rows: &'a Vec<Row>, syntax_file: Option<&JsonValue>) -> Self;
+  fn lex(rows: &'a [Row], syntax_file: Option<&JsonValue>) -> Self;
   fn parse(&self) -> Option<Vec<Vec<Parsed>>>;
+  // tokens for every row, available whether or not highlighting is on
+  fn tokenize(rows: &[Row]) -> Vec<Vec<Token>>;
 }
 
 fn get_color(color_str: &str) -> Option<Color> {
@@ -47,3 +119,29 @@ fn get_color(color_str: &str) -> Option<Color> {
     _ => None
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keywords_lex_as_keywords() {
+    // the lexers' patterns want some keywords followed by a blank, after a '&'
+    // or a '#', before a '!' or in parentheses
+    let contexts = [" {} ", "&{} ", "#{} ", "{}! ", "({})"];
+    for language in [Language::Rust, Language::Python, Language::C] {
+      for keyword in language.keywords() {
+        let lexed = contexts.iter().any(|context| {
+          let line = context.replace("{}", keyword);
+          let start = line.find(keyword).unwrap();
+          language.tokenize(&[Row::from(line.as_str())])[0].iter().any(|token| {
+            matches!(token.kind, TokenKind::Keyword | TokenKind::Type)
+              && token.range.start <= start
+              && start + keyword.len() <= token.range.end
+          })
+        });
+        assert!(lexed, "{:?} doesn't lex {} as a keyword", language, keyword)
+      }
+    }
+  }
+}
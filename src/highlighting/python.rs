@@ -1,7 +1,7 @@
 use crate::highlighting::{
   Lexer, Parsed, Row, Color,
   get_color, Attribute, Logos, LogosLexer,
-  JsonValue
+  JsonValue, Lexed, Token, TokenKind
 };
 
 fn trim_function(token: &mut LogosLexer<PythonToken>) -> String {
@@ -80,10 +80,57 @@ enum PythonToken {
   DontCare
 }
 
+pub const KEYWORDS: &[&str] = &[
+  "import", "from", "if", "else", "elif", "while", "for", "class", "break", "True",
+  "False", "continue", "return", "pass", "try", "except", "finally", "as", "def",
+  "raise", "async", "await", "in", "or", "with", "not", "and", "assert",
+  "dict", "list", "set", "int", "str", "float", "None", "bool", "bytes", "self"
+];
+
+fn lex_rows(rows: &[Row]) -> Lexed<PythonToken> {
+  let mut lex = Vec::new();
+  let mut multiline_flag = false;
+  for row in rows {
+    let mut row_lex = Vec::new();
+    let lexed = PythonToken::lexer(row.content()).spanned();
+    for token_range in lexed {
+      match token_range.0 {
+        PythonToken::MultiLineComment => {
+          multiline_flag = !multiline_flag;
+          row_lex.push(token_range)
+        },
+        PythonToken::Function(name) => {
+          if multiline_flag {
+            row_lex.push((PythonToken::Comment, token_range.1));
+            continue
+          }
+          row_lex.push((PythonToken::Function(name), std::ops::Range {
+            start: token_range.1.start,
+            end: token_range.1.end - 1
+          }));
+          row_lex.push((PythonToken::DontCare, std::ops::Range {
+            start: token_range.1.end - 1,
+            end: token_range.1.end
+          }))
+        },
+        _ => {
+          if multiline_flag {
+            row_lex.push((PythonToken::Comment, token_range.1));
+            continue
+          }
+          row_lex.push(token_range)
+        }
+      }
+    }
+    lex.push(row_lex)
+  }
+  lex
+}
+
 pub struct PythonLexer<'a> {
   _syntax: Option<json::JsonValue>,
-  _lex: Option<Vec<Vec<(PythonToken, std::ops::Range<usize>)>>>,
-  _raw: Option<&'a Vec<Row>>
+  _lex: Option<Lexed<PythonToken>>,
+  _raw: Option<&'a [Row]>
 }
 
 impl<'a> Lexer<'a> for PythonLexer<'a> {
@@ -96,44 +143,9 @@ impl<'a> Lexer<'a> for PythonLexer<'a> {
     }
   }
 
-  fn lex(rows: &'a Vec<Row>, syntax_file: Option<&JsonValue>) -> Self {
+  fn lex(rows: &'a [Row], syntax_file: Option<&JsonValue>) -> Self {
     if let Some(syntax) = syntax_file {
-      let mut lex = Vec::new();
-      let mut multiline_flag = false;
-      for row in rows {
-        let mut row_lex = Vec::new();
-        let lexed = PythonToken::lexer(row.content()).spanned();
-        for token_range in lexed {
-          match token_range.0 {
-            PythonToken::MultiLineComment => {
-              multiline_flag = !multiline_flag;
-              row_lex.push(token_range)
-            },
-            PythonToken::Function(name) => {
-              if multiline_flag {
-                row_lex.push((PythonToken::Comment, token_range.1));
-                continue
-              }
-              row_lex.push((PythonToken::Function(name), std::ops::Range {
-                start: token_range.1.start,
-                end: token_range.1.end - 1
-              }));
-              row_lex.push((PythonToken::DontCare, std::ops::Range {
-                start: token_range.1.end - 1,
-                end: token_range.1.end
-              }))
-            },
-            _ => {
-              if multiline_flag {
-                row_lex.push((PythonToken::Comment, token_range.1));
-                continue
-              }
-              row_lex.push(token_range)
-            }
-          }
-        }
-        lex.push(row_lex)
-      }
+      let lex = lex_rows(rows);
       Self {
         _lex: Some(lex),
         _syntax: Some(syntax.clone()),
@@ -146,17 +158,14 @@ impl<'a> Lexer<'a> for PythonLexer<'a> {
 
   // TODO: use syntax/rust.json to encode tokens & respective colors
   fn parse(&self) -> Option<Vec<Vec<Parsed>>> {
-    if self._lex == None {
-      return None
-    }
-    let lexed = self._lex.as_ref().unwrap();
+    let lexed = self._lex.as_ref()?;
     let mut parsed_file = Vec::new();
-    let mut raw_content_iter = self._raw.unwrap().into_iter();
+    let mut raw_content_iter = self._raw.unwrap().iter();
     for row in lexed {
       let raw_row = raw_content_iter.next().unwrap();
       let mut parsed_row = Vec::new();
       for (token, range) in row {
-        let original = String::from(raw_row.content()[range.clone()].to_string());
+        let original = raw_row.content()[range.clone()].to_string();
         parsed_row.push(Parsed {
           color: match_color(token, self._syntax.as_ref().unwrap()),
           range: range.clone(),
@@ -168,6 +177,33 @@ impl<'a> Lexer<'a> for PythonLexer<'a> {
     }
     Some(parsed_file)
   }
+
+  fn tokenize(rows: &[Row]) -> Vec<Vec<Token>> {
+    lex_rows(rows)
+      .into_iter()
+      .map(|row| row
+        .into_iter()
+        .map(|(token, range)| Token {
+          kind: token_kind(&token),
+          range
+        })
+        .collect())
+      .collect()
+  }
+}
+
+fn token_kind(token: &PythonToken) -> TokenKind {
+  match token {
+    PythonToken::Keyword => TokenKind::Keyword,
+    PythonToken::Type => TokenKind::Type,
+    PythonToken::String => TokenKind::String,
+    PythonToken::Number => TokenKind::Number,
+    PythonToken::Function(_) => TokenKind::Function,
+    PythonToken::Comment |
+    PythonToken::MultiLineComment => TokenKind::Comment,
+    PythonToken::Constant |
+    PythonToken::DontCare => TokenKind::Other
+  }
 }
 
 fn match_color(token: &PythonToken, syntax_rules: &JsonValue) -> Option<Color> {
@@ -1,7 +1,7 @@
 use crate::highlighting::{
   Lexer, Parsed, Row, Color,
   get_color, Attribute, Logos, LogosLexer,
-  JsonValue
+  JsonValue, Lexed, Token, TokenKind
 };
 
 fn trim_function(token: &mut LogosLexer<RustToken>) -> String {
@@ -15,7 +15,8 @@ enum RustToken {
   #[regex("\"([^\"]*)\"", priority=100)]
   String,
 
-  #[regex("\'([^\']*)\'")]
+  // a single character or escape, so a lifetime is never the start of one
+  #[regex(r#"'([^'\\]|\\[nrt0'"\\]|\\x[0-9a-fA-F][0-9a-fA-F]|\\u\{[0-9a-fA-F]+\})'"#)]
   Char,
 
   #[regex(r"-?[0-9]+(\.[0-9]+)?")]
@@ -76,10 +77,44 @@ enum RustToken {
   DontCare
 }
 
+pub const KEYWORDS: &[&str] = &[
+  "as", "fn", "impl", "for", "in", "use", "mod", "trait", "pub", "mut", "enum",
+  "let", "const", "true", "false", "break", "continue", "if", "else", "struct",
+  "macro_rules", "match", "dyn", "loop", "async", "return",
+  "u8", "u16", "u32", "u64", "u128", "i8", "i16", "i32", "i64", "i128", "self",
+  "Self", "Vec", "Option", "Result", "Ok", "Box", "String", "str", "None",
+  "usize", "char", "bool"
+];
+
+fn lex_rows(rows: &[Row]) -> Lexed<RustToken> {
+  let mut lex = Vec::new();
+  for row in rows {
+    let mut row_lex = Vec::new();
+    let lexed = RustToken::lexer(row.content()).spanned();
+    for token_range in lexed {
+      match token_range.0 {
+        RustToken::Function(name) => {
+          row_lex.push((RustToken::Function(name), std::ops::Range {
+            start: token_range.1.start,
+            end: token_range.1.end - 1
+          }));
+          row_lex.push((RustToken::DontCare, std::ops::Range {
+            start: token_range.1.end - 1,
+            end: token_range.1.end
+          }))
+        },
+        _ => row_lex.push(token_range)
+      }
+    }
+    lex.push(row_lex)
+  }
+  lex
+}
+
 pub struct RustLexer<'a> {
   _syntax: Option<json::JsonValue>,
-  _lex: Option<Vec<Vec<(RustToken, std::ops::Range<usize>)>>>,
-  _raw: Option<&'a Vec<Row>>
+  _lex: Option<Lexed<RustToken>>,
+  _raw: Option<&'a [Row]>
 }
 
 impl<'a> Lexer<'a> for RustLexer<'a> {
@@ -92,29 +127,9 @@ impl<'a> Lexer<'a> for RustLexer<'a> {
     }
   }
 
-  fn lex(rows: &'a Vec<Row>, syntax_file: Option<&JsonValue>) -> Self {
+  fn lex(rows: &'a [Row], syntax_file: Option<&JsonValue>) -> Self {
     if let Some(syntax) = syntax_file {
-      let mut lex = Vec::new();
-      for row in rows {
-        let mut row_lex = Vec::new();
-        let lexed = RustToken::lexer(row.content()).spanned();
-        for token_range in lexed {
-          match token_range.0 {
-            RustToken::Function(name) => {
-              row_lex.push((RustToken::Function(name), std::ops::Range {
-                start: token_range.1.start,
-                end: token_range.1.end - 1
-              }));
-              row_lex.push((RustToken::DontCare, std::ops::Range {
-                start: token_range.1.end - 1,
-                end: token_range.1.end
-              }))
-            },
-            _ => row_lex.push(token_range)
-          }
-        }
-        lex.push(row_lex)
-      }
+      let lex = lex_rows(rows);
       Self {
         _lex: Some(lex),
         _syntax: Some(syntax.clone()),
@@ -127,17 +142,14 @@ impl<'a> Lexer<'a> for RustLexer<'a> {
 
   // TODO: use syntax/rust.json to encode tokens & respective colors
   fn parse(&self) -> Option<Vec<Vec<Parsed>>> {
-    if self._lex == None {
-      return None
-    }
-    let lexed = self._lex.as_ref().unwrap();
+    let lexed = self._lex.as_ref()?;
     let mut parsed_file = Vec::new();
-    let mut raw_content_iter = self._raw.unwrap().into_iter();
+    let mut raw_content_iter = self._raw.unwrap().iter();
     for row in lexed {
       let raw_row = raw_content_iter.next().unwrap();
       let mut parsed_row = Vec::new();
       for (token, range) in row {
-        let original = String::from(raw_row.content()[range.clone()].to_string());
+        let original = raw_row.content()[range.clone()].to_string();
         parsed_row.push(Parsed {
           color: match_color(token, self._syntax.as_ref().unwrap()),
           range: range.clone(),
@@ -149,6 +161,32 @@ impl<'a> Lexer<'a> for RustLexer<'a> {
     }
     Some(parsed_file)
   }
+
+  fn tokenize(rows: &[Row]) -> Vec<Vec<Token>> {
+    lex_rows(rows)
+      .into_iter()
+      .map(|row| row
+        .into_iter()
+        .map(|(token, range)| Token {
+          kind: token_kind(&token),
+          range
+        })
+        .collect())
+      .collect()
+  }
+}
+
+fn token_kind(token: &RustToken) -> TokenKind {
+  match token {
+    RustToken::Keyword => TokenKind::Keyword,
+    RustToken::Type => TokenKind::Type,
+    RustToken::String => TokenKind::String,
+    RustToken::Char => TokenKind::Char,
+    RustToken::Number => TokenKind::Number,
+    RustToken::Function(_) => TokenKind::Function,
+    RustToken::Comment => TokenKind::Comment,
+    RustToken::DontCare => TokenKind::Other
+  }
 }
 
 fn match_color(token: &RustToken, syntax_rules: &JsonValue) -> Option<Color> {
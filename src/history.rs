@@ -4,6 +4,8 @@ use crate::file::{
   Row
 };
 
+// 'altered_rows' is the range the edit occupies in the document now,
+// 'rows' is what that range held before the edit (the two may differ in length)
 #[derive(Debug, PartialEq)]
 pub struct HistoryNode {
   pub altered_rows: Range<usize>,
@@ -22,6 +24,19 @@ impl HistoryNode {
     }
   }
 
+  // the node that takes 'after' back to 'before', covering only the rows that differ
+  pub fn between(before: &[Row], after: &[Row]) -> Option<Self> {
+    let prefix = before.iter().zip(after.iter()).take_while(|(old, new)| old == new).count();
+    if prefix == before.len() && prefix == after.len() {
+      return None
+    }
+    let suffix = before[prefix..].iter().rev()
+      .zip(after[prefix..].iter().rev())
+      .take_while(|(old, new)| old == new)
+      .count();
+    Some(Self::create(&before[prefix..(before.len() - suffix)], prefix..(after.len() - suffix)))
+  }
+
   pub fn extract(self) -> (Range<usize>, Vec<Row>) {
     (self.altered_rows, self.rows)
   }
@@ -33,7 +48,7 @@ pub struct History {
   maximum_size: usize
 }
 
-impl<'a> History {
+impl History {
   pub fn new() -> Self {
     Self {
       history: VecDeque::new(),
@@ -64,4 +79,15 @@ impl<'a> History {
   pub fn pop(&mut self) -> Option<HistoryNode> {
     self.history.pop_front()
   }
+
+  pub fn len(&self) -> usize {
+    self.history.len()
+  }
+
+  // drops everything pushed since the history had 'len' nodes
+  pub fn rollback_to(&mut self, len: usize) {
+    while self.history.len() > len {
+      self.history.pop_front();
+    }
+  }
 }
@@ -0,0 +1,202 @@
+use std::ops::Range;
+use crate::file::Row;
+use crate::highlighting::{Language, Token, TokenKind};
+
+// two spaces, as Tab inserts
+pub const WIDTH: usize = 2;
+
+// a line above the one being indented, as the rules see it
+#[derive(Debug, Clone, Copy)]
+pub struct Line<'a> {
+  pub indent: usize,
+  pub code: &'a str
+}
+
+// the text of a row without its comments, strings left empty and trimmed, so
+// brackets and colons in them don't count
+fn code(content: &str, tokens: &[Token]) -> String {
+  let mut code = String::new();
+  let mut idx = 0;
+  for token in tokens {
+    let literal = matches!(token.kind, TokenKind::String | TokenKind::Char | TokenKind::Comment);
+    if !literal || token.range.start < idx {
+      continue
+    }
+    code.push_str(&content[idx..token.range.start]);
+    if token.kind != TokenKind::Comment {
+      code.push_str("\"\"")
+    }
+    idx = token.range.end
+  }
+  code.push_str(&content[idx..]);
+  code.trim().to_string()
+}
+
+// the indentation and code of each of 'rows'
+pub fn lines(language: Option<Language>, rows: &[Row]) -> Vec<(usize, String)> {
+  let tokens = language.map(|language| language.tokenize(rows));
+  rows
+    .iter()
+    .enumerate()
+    .map(|(idx, row)| {
+      let indent = row.content().chars().take_while(|ch| ch.is_whitespace()).count();
+      let code = match &tokens {
+        Some(tokens) => code(row.content(), &tokens[idx]),
+        None => row.content().trim().to_string()
+      };
+      (indent, code)
+    })
+    .collect()
+}
+
+// those of 'lines' with code in them, blank lines saying nothing of indentation
+pub fn above(lines: &[(usize, String)]) -> Vec<Line<'_>> {
+  lines
+    .iter()
+    .filter(|(_, code)| !code.is_empty())
+    .map(|(indent, code)| Line { indent: *indent, code })
+    .collect()
+}
+
+fn first_word(code: &str) -> &str {
+  code.split(|ch: char| !(ch.is_alphanumeric() || ch == '_')).next().unwrap_or("")
+}
+
+// a C 'case' or 'default' label
+fn is_label(code: &str) -> bool {
+  matches!(first_word(code), "case" | "default") && code.ends_with(':')
+}
+
+// a C if, for, while or else whose body is the single statement below it
+fn is_bare_control(code: &str) -> bool {
+  matches!(first_word(code), "if" | "for" | "while" | "else") && !code.ends_with(['{', ';', '}'])
+}
+
+fn opens(code: &str) -> bool {
+  code.ends_with(['{', '(', '['])
+}
+
+// the indentation of the line holding the bracket a line starting with a
+// closing one closes
+fn opener_indent(above: &[Line]) -> Option<usize> {
+  let mut depth = 0;
+  for line in above.iter().rev() {
+    for ch in line.code.chars().rev() {
+      match ch {
+        '}' | ')' | ']' => depth += 1,
+        '{' | '(' | '[' if depth == 0 => return Some(line.indent),
+        '{' | '(' | '[' => depth -= 1,
+        _ => ()
+      }
+    }
+  }
+  None
+}
+
+// the indentation of a line whose code is 'line' below the lines 'above' it:
+// one step in after an opening bracket, back out to where a closing bracket
+// was opened, plus what each language adds
+pub fn indent(language: Option<Language>, above: &[Line], line: &str) -> usize {
+  let previous = match above.last() {
+    Some(previous) => previous,
+    None => return 0
+  };
+  if line.starts_with(['}', ')', ']']) {
+    if let Some(indent) = opener_indent(above) {
+      return indent
+    }
+  }
+  let before = above.len().checked_sub(2).map(|idx| above[idx]);
+  let mut indent = previous.indent;
+  if opens(previous.code) {
+    indent += WIDTH
+  }
+  match language {
+    Some(Language::Python) => {
+      if previous.code.ends_with(':') {
+        indent += WIDTH
+      }
+      if matches!(first_word(previous.code), "return" | "pass" | "break" | "continue" | "raise") {
+        indent = indent.saturating_sub(WIDTH)
+      }
+      if matches!(first_word(line), "else" | "elif" | "except" | "finally") {
+        indent = std::cmp::min(indent, previous.indent.saturating_sub(WIDTH))
+      }
+    },
+    Some(Language::C) => {
+      if is_label(previous.code) || is_bare_control(previous.code) {
+        indent += WIDTH
+      } else if let Some(before) = before.filter(|before| is_bare_control(before.code) && !opens(previous.code)) {
+        indent = before.indent
+      }
+      if is_label(line) && !opens(previous.code) {
+        indent = std::cmp::min(indent, previous.indent.saturating_sub(WIDTH))
+      }
+    },
+    Some(Language::Rust) => {
+      // a match arm whose body is on the line below it
+      if previous.code.ends_with("=>") {
+        indent += WIDTH
+      } else if let Some(before) = before.filter(|before| before.code.ends_with("=>") && !opens(previous.code)) {
+        indent = before.indent
+      }
+      // method calls chained on lines of their own go in a step from where
+      // the chain starts, and the line after them back to it
+      let chained = |code: &str| code.starts_with('.');
+      if chained(line) && !chained(previous.code) && !opens(previous.code) {
+        indent += WIDTH
+      } else if chained(previous.code) && !chained(line) && !opens(previous.code) {
+        if let Some(start) = above.iter().rev().find(|above| !chained(above.code)) {
+          indent = start.indent
+        }
+      }
+    },
+    None => ()
+  }
+  indent
+}
+
+// the indentation of each line in 'range' of a document made of 'lines',
+// each reindented line counting as above the next; Python's blocks end where
+// the indentation says, so there a line never goes further in than the line
+// it was level with before
+pub fn reindent(language: Option<Language>, lines: &[(usize, String)], range: Range<usize>) -> Vec<usize> {
+  let mut above: Vec<Line> = self::above(&lines[..range.start]);
+  // the indentation each line above had and has now
+  let mut levels: Vec<(usize, usize)> = above.iter().map(|line| (line.indent, line.indent)).collect();
+  let mut indents = Vec::new();
+  for (indent, code) in &lines[range] {
+    let mut new = self::indent(language, &above, code);
+    if language == Some(Language::Python) && !above.last().is_some_and(|previous| previous.code.ends_with(':')) {
+      let level = levels
+        .iter()
+        .rev()
+        .find(|(old, _)| old <= indent)
+        .map_or(0, |(old, new)| if old == indent { *new } else { new + WIDTH });
+      new = std::cmp::min(new, level)
+    }
+    indents.push(new);
+    if !code.is_empty() {
+      levels.push((*indent, new));
+      above.push(Line { indent: new, code })
+    }
+  }
+  indents
+}
+
+// whether typing 'key' after 'before', the row up to the cursor, makes the
+// row indent differently: a closing bracket or a chained call starting it,
+// a label or an else ending it
+pub fn electric(language: Option<Language>, before: &str, key: char) -> bool {
+  let before = before.trim();
+  match key {
+    '}' | ')' | ']' => before.is_empty(),
+    '.' => language == Some(Language::Rust) && before.is_empty(),
+    ':' => match language {
+      Some(Language::Python) => matches!(first_word(before), "else" | "elif" | "except" | "finally"),
+      Some(Language::C) => matches!(first_word(before), "case" | "default"),
+      _ => false
+    },
+    _ => false
+  }
+}
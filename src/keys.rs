@@ -0,0 +1,77 @@
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+// keys are kept as text so a recorded macro can be put and edited like
+// any other register; special keys use vim's <Name> notation
+const SPECIAL_KEYS: [(&str, KeyCode); 9] = [
+  ("Esc", KeyCode::Esc),
+  ("CR", KeyCode::Enter),
+  ("BS", KeyCode::Backspace),
+  ("Tab", KeyCode::Tab),
+  ("Up", KeyCode::Up),
+  ("Down", KeyCode::Down),
+  ("Left", KeyCode::Left),
+  ("Right", KeyCode::Right),
+  ("lt", KeyCode::Char('<'))
+];
+
+fn encode_key(key: &KeyEvent) -> Option<String> {
+  let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+  match key.code {
+    KeyCode::Char(ch) if ctrl => Some(format!("<C-{}>", ch)),
+    KeyCode::Char('<') => Some(String::from("<lt>")),
+    KeyCode::Char(ch) => Some(ch.to_string()),
+    code => SPECIAL_KEYS
+      .iter()
+      .find(|(_, special)| *special == code)
+      .map(|(name, _)| format!("<{}>", name))
+  }
+}
+
+pub fn encode(event: &Event) -> Option<String> {
+  match event {
+    Event::Key(key) => encode_key(key),
+    Event::Paste(text) => Some(text.replace('<', "<lt>")),
+    _ => None
+  }
+}
+
+fn char_event(ch: char) -> Event {
+  match ch {
+    '\n' | '\r' => Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)),
+    '\t' => Event::Key(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE)),
+    ch if ch.is_uppercase() => Event::Key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::SHIFT)),
+    ch => Event::Key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE))
+  }
+}
+
+fn decode_special(name: &str) -> Option<Event> {
+  if let Some(ch) = name.strip_prefix("C-").and_then(|rest| rest.chars().next()) {
+    return Some(Event::Key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::CONTROL)))
+  }
+  SPECIAL_KEYS
+    .iter()
+    .find(|(special, _)| *special == name)
+    .map(|(_, code)| match code {
+      KeyCode::Char(ch) => char_event(*ch),
+      code => Event::Key(KeyEvent::new(*code, KeyModifiers::NONE))
+    })
+}
+
+pub fn decode(text: &str) -> Vec<Event> {
+  let mut events = Vec::new();
+  let mut rest = text;
+  while let Some(ch) = rest.chars().next() {
+    if ch == '<' {
+      if let Some(end) = rest.find('>') {
+        if let Some(event) = decode_special(&rest[1..end]) {
+          events.push(event);
+          rest = &rest[(end + 1)..];
+          continue
+        }
+      }
+    }
+    events.push(char_event(ch));
+    rest = &rest[ch.len_utf8()..]
+  }
+  events
+}
@@ -0,0 +1,802 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use json::{array, object, JsonValue};
+use dirs::home_dir;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::diagnostics::Severity;
+use crate::highlighting::Language;
+use crate::motion::Position;
+use crate::recent;
+
+// how a language server is started, from ~/.editrc/lsp.json or the defaults
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+  pub command: String,
+  pub args: Vec<String>
+}
+
+pub fn language_id(language: Language) -> &'static str {
+  match language {
+    Language::Rust => "rust",
+    Language::Python => "python",
+    Language::C => "c"
+  }
+}
+
+// the server for 'language': an entry in ~/.editrc/lsp.json such as
+// { "rust": { "command": "rust-analyzer", "args": [] } }, where null turns
+// the language off; languages without an entry use a well known server
+pub fn server_config(language: Language) -> Option<ServerConfig> {
+  let config = home_dir()
+    .and_then(|home| std::fs::read_to_string(home.join(".editrc/lsp.json")).ok())
+    .and_then(|content| json::parse(&content).ok())
+    .unwrap_or(JsonValue::Null);
+  let entry = &config[language_id(language)];
+  if config.has_key(language_id(language)) {
+    return Some(ServerConfig {
+      command: entry["command"].as_str()?.to_string(),
+      args: entry["args"].members().filter_map(|arg| arg.as_str().map(String::from)).collect()
+    })
+  }
+  let (command, args): (&str, &[&str]) = match language {
+    Language::Rust => ("rust-analyzer", &[]),
+    Language::Python => ("pyright-langserver", &["--stdio"]),
+    Language::C => ("clangd", &[])
+  };
+  Some(ServerConfig {
+    command: command.to_string(),
+    args: args.iter().map(|arg| arg.to_string()).collect()
+  })
+}
+
+// LSP positions count UTF-16 code units, the editor counts graphemes
+pub fn to_utf16(line: &str, column: usize) -> usize {
+  line.graphemes(true).take(column).map(|grapheme| grapheme.encode_utf16().count()).sum()
+}
+
+pub fn to_column(line: &str, utf16: usize) -> usize {
+  let mut units = 0;
+  line.graphemes(true).take_while(|grapheme| {
+    units += grapheme.encode_utf16().count();
+    units <= utf16
+  }).count()
+}
+
+fn byte_offset(line: &str, utf16: usize) -> usize {
+  let mut units = 0;
+  for (idx, ch) in line.char_indices() {
+    if units >= utf16 {
+      return idx
+    }
+    units += ch.len_utf16()
+  }
+  line.len()
+}
+
+pub fn path_to_uri(file_name: &str) -> String {
+  let path = Path::new(file_name);
+  let absolute = path.canonicalize().unwrap_or_else(|_| std::env::current_dir().unwrap_or_default().join(path));
+  let mut uri = String::from("file://");
+  for byte in absolute.to_string_lossy().bytes() {
+    match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+      byte => uri.push_str(&format!("%{:02X}", byte))
+    }
+  }
+  uri
+}
+
+// the file a uri names, relative to the working directory when inside it
+pub fn uri_to_path(uri: &str) -> Option<String> {
+  let encoded = uri.strip_prefix("file://")?.as_bytes();
+  let mut bytes = Vec::new();
+  let mut idx = 0;
+  while idx < encoded.len() {
+    let decoded = match encoded[idx] {
+      b'%' => encoded
+        .get((idx + 1)..(idx + 3))
+        .and_then(|hex| std::str::from_utf8(hex).ok())
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+      _ => None
+    };
+    match decoded {
+      Some(byte) => {
+        bytes.push(byte);
+        idx += 3
+      },
+      None => {
+        bytes.push(encoded[idx]);
+        idx += 1
+      }
+    }
+  }
+  Some(recent::display(&String::from_utf8_lossy(&bytes)))
+}
+
+// positions are lines and UTF-16 columns, as the server sent them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+  pub start: (usize, usize),
+  pub end: (usize, usize),
+  pub severity: Severity,
+  pub message: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LspLocation {
+  pub file: String,
+  pub start: (usize, usize)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+  pub start: (usize, usize),
+  pub end: (usize, usize),
+  pub text: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletionItem {
+  pub label: String,
+  pub detail: String,
+  pub text: String,
+  // where the text replaces from, when the server says so
+  pub start: Option<(usize, usize)>
+}
+
+// what the editor is told about once the server has answered
+#[derive(Debug, Clone, PartialEq)]
+pub enum LspReply {
+  Hover(String),
+  Definition(Vec<LspLocation>),
+  References(Vec<LspLocation>),
+  Rename(Vec<(String, Vec<TextEdit>)>),
+  Completion(Vec<CompletionItem>),
+  // the diagnostics of a file changed
+  Diagnostics(String),
+  Message(String)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Request {
+  Initialize,
+  Shutdown,
+  Hover,
+  Definition,
+  References,
+  Rename,
+  Completion
+}
+
+// replaces the ranges of 'edits' in 'lines', which do not overlap
+pub fn apply_edits(lines: &[String], edits: &[TextEdit]) -> Vec<String> {
+  let mut text = lines.join("\n");
+  let mut starts = vec![0];
+  for line in lines {
+    starts.push(starts.last().unwrap() + line.len() + 1)
+  }
+  let offset = |(line, utf16): (usize, usize)| match lines.get(line) {
+    Some(content) => starts[line] + byte_offset(content, utf16),
+    None => text.len()
+  };
+  let mut edits: Vec<(usize, usize, &str)> = edits
+    .iter()
+    .map(|edit| (offset(edit.start), offset(edit.end), edit.text.as_str()))
+    .collect();
+  // later edits first so the earlier offsets stay valid; edits at the same
+  // place go in reverse so they end up in the order they were given
+  edits.sort_by_key(|(start, _, _)| *start);
+  for (start, end, replacement) in edits.into_iter().rev() {
+    let end = std::cmp::min(std::cmp::max(start, end), text.len());
+    text.replace_range(start..end, replacement)
+  }
+  text.split('\n').map(String::from).collect()
+}
+
+fn position(value: &JsonValue) -> (usize, usize) {
+  (value["line"].as_usize().unwrap_or(0), value["character"].as_usize().unwrap_or(0))
+}
+
+fn text_edit(value: &JsonValue) -> TextEdit {
+  TextEdit {
+    start: position(&value["range"]["start"]),
+    end: position(&value["range"]["end"]),
+    text: value["newText"].as_str().unwrap_or("").to_string()
+  }
+}
+
+// a Location or a LocationLink
+fn location(value: &JsonValue) -> Option<LspLocation> {
+  let (uri, range) = if value.has_key("targetUri") {
+    (&value["targetUri"], &value["targetSelectionRange"])
+  } else {
+    (&value["uri"], &value["range"])
+  };
+  Some(LspLocation {
+    file: uri_to_path(uri.as_str()?)?,
+    start: position(&range["start"])
+  })
+}
+
+fn locations(result: &JsonValue) -> Vec<LspLocation> {
+  if result.is_array() {
+    result.members().filter_map(location).collect()
+  } else {
+    location(result).into_iter().collect()
+  }
+}
+
+// MarkedString, MarkedString[] or MarkupContent
+fn hover_text(contents: &JsonValue) -> String {
+  if let Some(text) = contents.as_str() {
+    text.to_string()
+  } else if contents.is_array() {
+    contents.members().map(hover_text).collect::<Vec<String>>().join("\n")
+  } else {
+    contents["value"].as_str().unwrap_or("").to_string()
+  }
+}
+
+fn workspace_edit(result: &JsonValue) -> Vec<(String, Vec<TextEdit>)> {
+  let mut files = Vec::new();
+  for (uri, edits) in result["changes"].entries() {
+    if let Some(file) = uri_to_path(uri) {
+      files.push((file, edits.members().map(text_edit).collect()))
+    }
+  }
+  // creating, renaming and deleting files is left out
+  for change in result["documentChanges"].members().filter(|change| change.has_key("edits")) {
+    if let Some(file) = change["textDocument"]["uri"].as_str().and_then(uri_to_path) {
+      files.push((file, change["edits"].members().map(text_edit).collect()))
+    }
+  }
+  files
+}
+
+fn completion_item(value: &JsonValue) -> CompletionItem {
+  let label = value["label"].as_str().unwrap_or("").to_string();
+  // a TextEdit, or an InsertReplaceEdit with an insert range instead
+  let edit = &value["textEdit"];
+  let range = if edit.has_key("insert") { &edit["insert"] } else { &edit["range"] };
+  CompletionItem {
+    detail: value["detail"].as_str().unwrap_or("").to_string(),
+    text: edit["newText"].as_str().or_else(|| value["insertText"].as_str()).unwrap_or(&label).to_string(),
+    start: if range.is_null() { None } else { Some(position(&range["start"])) },
+    label
+  }
+}
+
+fn read_messages(stdout: ChildStdout, sender: Sender<JsonValue>) {
+  let mut reader = BufReader::new(stdout);
+  loop {
+    let mut length = None;
+    loop {
+      let mut header = String::new();
+      if reader.read_line(&mut header).unwrap_or(0) == 0 {
+        return
+      }
+      let header = header.trim();
+      if header.is_empty() {
+        break
+      }
+      if let Some(value) = header.strip_prefix("Content-Length:") {
+        length = value.trim().parse().ok()
+      }
+    }
+    let mut body = vec![0; match length {
+      Some(length) => length,
+      None => continue
+    }];
+    if reader.read_exact(&mut body).is_err() {
+      return
+    }
+    if let Ok(message) = json::parse(&String::from_utf8_lossy(&body)) {
+      if sender.send(message).is_err() {
+        return
+      }
+    }
+  }
+}
+
+#[derive(Debug)]
+struct OpenDocument {
+  uri: String,
+  version: i64,
+  // the lines as the server has them
+  lines: Vec<String>
+}
+
+// a language server running as a child process spoken to over stdio; its
+// messages are read on another thread and handled whenever 'receive' is called
+#[derive(Debug)]
+pub struct LspClient {
+  pub language: Language,
+  process: Child,
+  stdin: ChildStdin,
+  receiver: Receiver<JsonValue>,
+  running: bool,
+  next_id: u64,
+  pending: HashMap<u64, Request>,
+  // nothing but initialize may be sent before the server has answered it
+  initialized: bool,
+  queued: Vec<JsonValue>,
+  incremental: bool,
+  document: Option<OpenDocument>,
+  // by file name
+  pub diagnostics: HashMap<String, Vec<Diagnostic>>
+}
+
+impl LspClient {
+  pub fn start(language: Language, config: &ServerConfig) -> std::io::Result<Self> {
+    let mut process = Command::new(&config.command)
+      .args(&config.args)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::null())
+      .spawn()?;
+    let stdin = process.stdin.take().unwrap();
+    let stdout = process.stdout.take().unwrap();
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || read_messages(stdout, sender));
+    let mut client = Self {
+      language,
+      process,
+      stdin,
+      receiver,
+      running: true,
+      next_id: 0,
+      pending: HashMap::new(),
+      initialized: false,
+      queued: Vec::new(),
+      incremental: false,
+      document: None,
+      diagnostics: HashMap::new()
+    };
+    let root = path_to_uri(".");
+    client.request(Request::Initialize, "initialize", object!{
+      "processId" => std::process::id(),
+      "rootUri" => root.as_str(),
+      "workspaceFolders" => array![object!{ "uri" => root.as_str(), "name" => "root" }],
+      "capabilities" => object!{
+        "general" => object!{ "positionEncodings" => array!["utf-16"] },
+        "textDocument" => object!{
+          "synchronization" => object!{ "didSave" => true },
+          "hover" => object!{ "contentFormat" => array!["plaintext", "markdown"] },
+          "definition" => object!{ "linkSupport" => true },
+          "references" => object!{},
+          "rename" => object!{},
+          "completion" => object!{ "completionItem" => object!{ "snippetSupport" => false } },
+          "publishDiagnostics" => object!{}
+        }
+      }
+    })?;
+    Ok(client)
+  }
+
+  pub fn is_running(&self) -> bool {
+    self.running
+  }
+
+  fn write(&mut self, message: &JsonValue) -> std::io::Result<()> {
+    let body = message.dump();
+    write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    self.stdin.flush()
+  }
+
+  fn send(&mut self, message: JsonValue) -> std::io::Result<()> {
+    if !self.initialized && message["method"] != "initialize" {
+      self.queued.push(message);
+      return Ok(())
+    }
+    let written = self.write(&message);
+    if written.is_err() {
+      self.running = false
+    }
+    written
+  }
+
+  fn request(&mut self, kind: Request, method: &str, params: JsonValue) -> std::io::Result<()> {
+    self.next_id += 1;
+    self.pending.insert(self.next_id, kind);
+    let mut message = object!{ "jsonrpc" => "2.0", "id" => self.next_id, "method" => method };
+    // a request without parameters leaves them out rather than send null
+    if !params.is_null() {
+      message["params"] = params
+    }
+    self.send(message)
+  }
+
+  fn notify(&mut self, method: &str, params: JsonValue) -> std::io::Result<()> {
+    let mut message = object!{ "jsonrpc" => "2.0", "method" => method };
+    if !params.is_null() {
+      message["params"] = params
+    }
+    self.send(message)
+  }
+
+  // tells the server 'file_name' is now the open document, closing the previous one
+  pub fn open(&mut self, file_name: &str, lines: Vec<String>) -> std::io::Result<()> {
+    self.close()?;
+    let uri = path_to_uri(file_name);
+    let mut text = lines.join("\n");
+    text.push('\n');
+    self.notify("textDocument/didOpen", object!{
+      "textDocument" => object!{
+        "uri" => uri.clone(),
+        "languageId" => language_id(self.language),
+        "version" => 1,
+        "text" => text
+      }
+    })?;
+    self.document = Some(OpenDocument { uri, version: 1, lines });
+    Ok(())
+  }
+
+  pub fn close(&mut self) -> std::io::Result<()> {
+    match self.document.take() {
+      Some(document) => self.notify("textDocument/didClose", object!{ "textDocument" => object!{ "uri" => document.uri } }),
+      None => Ok(())
+    }
+  }
+
+  pub fn has_document(&self, file_name: &str) -> bool {
+    self.document.as_ref().is_some_and(|document| document.uri == path_to_uri(file_name))
+  }
+
+  // sends whatever changed since the server last heard of the document, as the
+  // rows between the unchanged ones at either end
+  pub fn sync(&mut self, lines: &[&str]) -> std::io::Result<()> {
+    let document = match &mut self.document {
+      Some(document) => document,
+      None => return Ok(())
+    };
+    let old = &document.lines;
+    let prefix = old.iter().zip(lines.iter()).take_while(|(old, new)| old == *new).count();
+    if prefix == old.len() && prefix == lines.len() {
+      return Ok(())
+    }
+    let longest = std::cmp::min(old.len(), lines.len()) - prefix;
+    let suffix = old.iter().rev().zip(lines.iter().rev()).take(longest).take_while(|(old, new)| old == *new).count();
+    let change = if self.incremental {
+      let text: String = lines[prefix..(lines.len() - suffix)].iter().map(|line| format!("{}\n", line)).collect();
+      object!{
+        "range" => object!{
+          "start" => object!{ "line" => prefix, "character" => 0 },
+          "end" => object!{ "line" => old.len() - suffix, "character" => 0 }
+        },
+        "text" => text
+      }
+    } else {
+      let mut text = lines.join("\n");
+      text.push('\n');
+      object!{ "text" => text }
+    };
+    document.version += 1;
+    document.lines = lines.iter().map(|line| line.to_string()).collect();
+    let params = object!{
+      "textDocument" => object!{ "uri" => document.uri.clone(), "version" => document.version },
+      "contentChanges" => array![change]
+    };
+    self.notify("textDocument/didChange", params)
+  }
+
+  pub fn saved(&mut self) -> std::io::Result<()> {
+    match &self.document {
+      Some(document) => {
+        let uri = document.uri.clone();
+        self.notify("textDocument/didSave", object!{ "textDocument" => object!{ "uri" => uri } })
+      },
+      None => Ok(())
+    }
+  }
+
+  // the open document and a position in it, with the column in UTF-16 units
+  fn position_params(&self, (row, column): Position) -> JsonValue {
+    let (uri, line) = match &self.document {
+      Some(document) => (document.uri.clone(), document.lines.get(row).map(String::as_str).unwrap_or("")),
+      None => (String::new(), "")
+    };
+    object!{
+      "textDocument" => object!{ "uri" => uri },
+      "position" => object!{ "line" => row, "character" => to_utf16(line, column) }
+    }
+  }
+
+  pub fn hover(&mut self, pos: Position) -> std::io::Result<()> {
+    let params = self.position_params(pos);
+    self.request(Request::Hover, "textDocument/hover", params)
+  }
+
+  pub fn definition(&mut self, pos: Position) -> std::io::Result<()> {
+    let params = self.position_params(pos);
+    self.request(Request::Definition, "textDocument/definition", params)
+  }
+
+  pub fn references(&mut self, pos: Position) -> std::io::Result<()> {
+    let mut params = self.position_params(pos);
+    params["context"] = object!{ "includeDeclaration" => true };
+    self.request(Request::References, "textDocument/references", params)
+  }
+
+  pub fn rename(&mut self, pos: Position, name: &str) -> std::io::Result<()> {
+    let mut params = self.position_params(pos);
+    params["newName"] = name.into();
+    self.request(Request::Rename, "textDocument/rename", params)
+  }
+
+  pub fn completion(&mut self, pos: Position) -> std::io::Result<()> {
+    let params = self.position_params(pos);
+    self.request(Request::Completion, "textDocument/completion", params)
+  }
+
+  // handles everything the server has sent so far
+  pub fn receive(&mut self) -> Vec<LspReply> {
+    let mut replies = Vec::new();
+    loop {
+      match self.receiver.try_recv() {
+        Ok(message) => replies.extend(self.handle(message)),
+        Err(TryRecvError::Empty) => break,
+        Err(TryRecvError::Disconnected) => {
+          if self.running {
+            replies.push(LspReply::Message(format!("{} language server exited", language_id(self.language))))
+          }
+          self.running = false;
+          break
+        }
+      }
+    }
+    replies
+  }
+
+  fn handle(&mut self, message: JsonValue) -> Option<LspReply> {
+    let method = message["method"].as_str().map(String::from);
+    match (message.has_key("id"), method) {
+      // requests from the server get an empty answer, configuration one per item
+      (true, Some(method)) => {
+        let result = if method == "workspace/configuration" {
+          JsonValue::Array(message["params"]["items"].members().map(|_| JsonValue::Null).collect())
+        } else {
+          JsonValue::Null
+        };
+        let _ = self.send(object!{ "jsonrpc" => "2.0", "id" => message["id"].clone(), "result" => result });
+        None
+      },
+      (true, None) => {
+        let kind = self.pending.remove(&message["id"].as_u64()?)?;
+        if message.has_key("error") {
+          let why = message["error"]["message"].as_str().unwrap_or("request failed");
+          return Some(LspReply::Message(why.to_string()))
+        }
+        self.response(kind, &message["result"])
+      },
+      (false, Some(method)) => self.notification(&method, &message["params"]),
+      (false, None) => None
+    }
+  }
+
+  fn response(&mut self, kind: Request, result: &JsonValue) -> Option<LspReply> {
+    match kind {
+      Request::Initialize => {
+        let sync = &result["capabilities"]["textDocumentSync"];
+        let sync = if sync.is_object() { &sync["change"] } else { sync };
+        self.incremental = sync.as_u8() == Some(2);
+        self.initialized = true;
+        let _ = self.notify("initialized", object!{});
+        for message in std::mem::take(&mut self.queued) {
+          let _ = self.send(message);
+        }
+        None
+      },
+      Request::Shutdown => None,
+      Request::Hover => Some(LspReply::Hover(hover_text(&result["contents"]))),
+      Request::Definition => Some(LspReply::Definition(locations(result))),
+      Request::References => Some(LspReply::References(locations(result))),
+      Request::Rename => Some(LspReply::Rename(workspace_edit(result))),
+      Request::Completion => {
+        let items = if result.is_array() { result } else { &result["items"] };
+        Some(LspReply::Completion(items.members().map(completion_item).collect()))
+      }
+    }
+  }
+
+  fn notification(&mut self, method: &str, params: &JsonValue) -> Option<LspReply> {
+    match method {
+      "textDocument/publishDiagnostics" => {
+        let file = uri_to_path(params["uri"].as_str()?)?;
+        let diagnostics = params["diagnostics"].members().map(|diagnostic| Diagnostic {
+          start: position(&diagnostic["range"]["start"]),
+          end: position(&diagnostic["range"]["end"]),
+          severity: match diagnostic["severity"].as_u8() {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            _ => Severity::Error
+          },
+          message: diagnostic["message"].as_str().unwrap_or("").to_string()
+        }).collect();
+        self.diagnostics.insert(file.clone(), diagnostics);
+        Some(LspReply::Diagnostics(file))
+      },
+      // errors and warnings only, the rest is chatter
+      "window/showMessage" if params["type"].as_u8().is_some_and(|kind| kind <= 2) => {
+        Some(LspReply::Message(params["message"].as_str()?.to_string()))
+      },
+      _ => None
+    }
+  }
+}
+
+impl Drop for LspClient {
+  fn drop(&mut self) {
+    if self.running && self.initialized {
+      let _ = self.request(Request::Shutdown, "shutdown", JsonValue::Null);
+      let _ = self.notify("exit", JsonValue::Null);
+      // a moment to exit cleanly before it is made to
+      for _ in 0..20 {
+        if let Ok(Some(_)) = self.process.try_wait() {
+          return
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10))
+      }
+    }
+    let _ = self.process.kill();
+    let _ = self.process.wait();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::{Duration, Instant};
+
+  // answers every request from a table and logs each message it is sent, one
+  // JSON object per line, to the file named by its argument
+  const STUB_SERVER: &str = r#"
+import json, sys
+results = {
+  "initialize": {"capabilities": {"textDocumentSync": {"openClose": True, "change": 2}}},
+  "textDocument/hover": {"contents": [{"language": "rust", "value": "fn main()"}, "the entry point"]},
+  "textDocument/definition": [{
+    "targetUri": "file:///tmp/a%20b.rs",
+    "targetRange": {"start": {"line": 3, "character": 0}, "end": {"line": 5, "character": 1}},
+    "targetSelectionRange": {"start": {"line": 3, "character": 3}, "end": {"line": 3, "character": 7}}
+  }],
+  "textDocument/rename": {
+    "changes": {"file:///tmp/a.rs": [
+      {"range": {"start": {"line": 0, "character": 3}, "end": {"line": 0, "character": 7}}, "newText": "start"}
+    ]},
+    "documentChanges": [{
+      "textDocument": {"uri": "file:///tmp/b.rs", "version": 1},
+      "edits": [{"range": {"start": {"line": 2, "character": 0}, "end": {"line": 2, "character": 4}}, "newText": "start"}]
+    }]
+  }
+}
+log = open(sys.argv[1], "w")
+while True:
+  length = None
+  while True:
+    header = sys.stdin.buffer.readline()
+    if not header:
+      sys.exit(0)
+    if not header.strip():
+      break
+    if header.startswith(b"Content-Length:"):
+      length = int(header.split(b":")[1])
+  message = json.loads(sys.stdin.buffer.read(length))
+  log.write(json.dumps(message) + "\n")
+  log.flush()
+  if message.get("method") == "exit":
+    sys.exit(0)
+  if "id" in message:
+    body = json.dumps({"jsonrpc": "2.0", "id": message["id"], "result": results.get(message["method"])}).encode()
+    sys.stdout.buffer.write(b"Content-Length: %d\r\n\r\n" % len(body) + body)
+    sys.stdout.buffer.flush()
+"#;
+
+  fn receive(client: &mut LspClient, count: usize) -> Vec<LspReply> {
+    let mut replies = Vec::new();
+    let start = Instant::now();
+    while replies.len() < count && start.elapsed() < Duration::from_secs(10) {
+      replies.extend(client.receive());
+      std::thread::sleep(Duration::from_millis(10))
+    }
+    replies
+  }
+
+  #[test]
+  fn talks_to_a_server() {
+    let log = std::env::temp_dir().join(format!("silver-lsp-{}.log", std::process::id()));
+    let config = ServerConfig {
+      command: String::from("python3"),
+      args: vec![String::from("-c"), String::from(STUB_SERVER), log.to_string_lossy().to_string()]
+    };
+    let mut client = LspClient::start(Language::Rust, &config).expect("the stub server runs on python3");
+    let lines = vec![String::from("fn main() {"), String::from("  let 😀 = 1;"), String::from("}")];
+    client.open("src/main.rs", lines).unwrap();
+    client.hover((1, 8)).unwrap();
+    assert_eq!(receive(&mut client, 1), vec![LspReply::Hover(String::from("fn main()\nthe entry point"))]);
+
+    client.sync(&["fn main() {", "  let 😀 = 2;", "  main();", "}"]).unwrap();
+    client.definition((0, 3)).unwrap();
+    client.rename((0, 3), "start").unwrap();
+    let replies = receive(&mut client, 2);
+    assert_eq!(replies, vec![
+      LspReply::Definition(vec![LspLocation { file: String::from("/tmp/a b.rs"), start: (3, 3) }]),
+      LspReply::Rename(vec![
+        (String::from("/tmp/a.rs"), vec![TextEdit { start: (0, 3), end: (0, 7), text: String::from("start") }]),
+        (String::from("/tmp/b.rs"), vec![TextEdit { start: (2, 0), end: (2, 4), text: String::from("start") }])
+      ])
+    ]);
+    drop(client);
+
+    let messages: Vec<JsonValue> = std::fs::read_to_string(&log)
+      .unwrap()
+      .lines()
+      .map(|line| json::parse(line).unwrap())
+      .collect();
+    std::fs::remove_file(&log).unwrap();
+    let methods: Vec<&str> = messages.iter().filter_map(|message| message["method"].as_str()).collect();
+    // didOpen and hover were held back until the server had answered initialize
+    assert_eq!(methods, vec![
+      "initialize", "initialized", "textDocument/didOpen", "textDocument/hover", "textDocument/didChange",
+      "textDocument/definition", "textDocument/rename", "shutdown", "exit"
+    ]);
+    assert_eq!(messages[0]["params"]["capabilities"]["general"]["positionEncodings"][0], "utf-16");
+    assert_eq!(messages[2]["params"]["textDocument"]["text"], "fn main() {\n  let 😀 = 1;\n}\n");
+    // the emoji is two UTF-16 units
+    assert_eq!(messages[3]["params"]["position"]["character"], 9);
+    let change = &messages[4]["params"];
+    assert_eq!(change["textDocument"]["version"], 2);
+    assert_eq!(change["contentChanges"][0]["range"]["start"]["line"], 1);
+    assert_eq!(change["contentChanges"][0]["range"]["end"]["line"], 2);
+    assert_eq!(change["contentChanges"][0]["text"], "  let 😀 = 2;\n  main();\n");
+  }
+
+  fn edit(start: (usize, usize), end: (usize, usize), text: &str) -> TextEdit {
+    TextEdit { start, end, text: text.to_string() }
+  }
+
+  fn lines(text: &[&str]) -> Vec<String> {
+    text.iter().map(|line| line.to_string()).collect()
+  }
+
+  #[test]
+  fn applies_edits() {
+    let text = lines(&["fn main() {", "  let 😀x = 1;", "}"]);
+    // given out of order, with the emoji counted as two units
+    let edits = [edit((1, 8), (1, 9), "y"), edit((0, 3), (0, 7), "start")];
+    assert_eq!(apply_edits(&text, &edits), lines(&["fn start() {", "  let 😀y = 1;", "}"]));
+    // across lines, and past the end of the document
+    assert_eq!(apply_edits(&text, &[edit((0, 11), (2, 0), " ")]), lines(&["fn main() { }"]));
+    assert_eq!(apply_edits(&text, &[edit((3, 0), (3, 0), "\n")]), lines(&["fn main() {", "  let 😀x = 1;", "}", ""]));
+    // insertions at the same place keep their order
+    let edits = [edit((2, 0), (2, 0), "a"), edit((2, 0), (2, 0), "b")];
+    assert_eq!(apply_edits(&text, &edits)[2], "ab}");
+  }
+
+  #[test]
+  fn converts_columns() {
+    let line = "a😀e\u{301}b";
+    assert_eq!(to_utf16(line, 0), 0);
+    assert_eq!(to_utf16(line, 2), 3);
+    assert_eq!(to_utf16(line, 3), 5);
+    assert_eq!(to_utf16(line, 10), 6);
+    assert_eq!(to_column(line, 3), 2);
+    // inside the emoji or the accented e is the grapheme they belong to
+    assert_eq!(to_column(line, 2), 1);
+    assert_eq!(to_column(line, 4), 2);
+    assert_eq!(to_column(line, 6), 4);
+  }
+
+  #[test]
+  fn reads_uris() {
+    assert_eq!(uri_to_path("file:///tmp/a%20b%C3%A9.rs"), Some(String::from("/tmp/a bé.rs")));
+    assert_eq!(uri_to_path("file:///tmp/100%"), Some(String::from("/tmp/100%")));
+    assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+    // files under the working directory are given relative to it
+    assert_eq!(uri_to_path(&path_to_uri("src/lsp.rs")), Some(String::from("src/lsp.rs")));
+  }
+}
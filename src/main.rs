@@ -1,7 +1,29 @@
+mod clipboard;
 mod editor;
 mod file;
 mod history;
+mod keys;
+mod marks;
 mod highlighting;
+mod registers;
+mod motion;
+mod normal;
+mod textobject;
+mod substitute;
+mod search;
+mod quickfix;
+mod grep;
+mod replace;
+mod picker;
+mod explorer;
+mod recent;
+mod lsp;
+mod diagnostics;
+mod make;
+mod completion;
+mod snippet;
+mod pairs;
+mod indent;
 
 use editor::Editor;
 
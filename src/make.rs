@@ -0,0 +1,142 @@
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use regex::Regex;
+use crate::diagnostics::Severity;
+use crate::quickfix::QuickfixEntry;
+use crate::recent;
+
+// a location the build complained about
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildError {
+  pub entry: QuickfixEntry,
+  pub severity: Severity,
+  pub message: String
+}
+
+// a build command running in the background, what it writes to stdout and
+// stderr collected line by line
+#[derive(Debug)]
+pub struct Build {
+  pub command: String,
+  pub output: Vec<String>,
+  child: Child,
+  lines: Receiver<String>
+}
+
+// what :make runs unless 'set makeprg' says otherwise, guessed from the project
+pub fn default_program() -> &'static str {
+  if Path::new("Cargo.toml").exists() {
+    "cargo build"
+  } else {
+    "make"
+  }
+}
+
+impl Build {
+  pub fn start(command: &str) -> std::io::Result<Self> {
+    let mut child = Command::new("sh")
+      .arg("-c")
+      .arg(format!("exec 2>&1; {}", command))
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::null())
+      .spawn()?;
+    let stdout = child.stdout.take().unwrap();
+    let (sender, lines) = channel();
+    std::thread::spawn(move || {
+      for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        // nobody is listening once the editor has quit
+        if sender.send(line).is_err() {
+          return
+        }
+      }
+    });
+    Ok(Self {
+      command: command.to_string(),
+      output: Vec::new(),
+      child,
+      lines
+    })
+  }
+
+  // takes what the build has printed so far, its exit status once it is over
+  pub fn receive(&mut self) -> Option<ExitStatus> {
+    loop {
+      match self.lines.try_recv() {
+        Ok(line) => self.output.push(line),
+        Err(TryRecvError::Empty) => return None,
+        Err(TryRecvError::Disconnected) => return self.child.try_wait().ok().flatten()
+      }
+    }
+  }
+}
+
+impl Drop for Build {
+  fn drop(&mut self) {
+    if let Ok(None) = self.child.try_wait() {
+      let _ = self.child.kill();
+      let _ = self.child.wait();
+    }
+  }
+}
+
+fn severity(word: &str) -> Severity {
+  match word {
+    "warning" => Severity::Warning,
+    "note" => Severity::Information,
+    _ => Severity::Error
+  }
+}
+
+// line and column are counted from 1 in compiler output
+fn build_error(file: &str, line: &str, column: Option<&str>, severity: Severity, text: &str) -> BuildError {
+  let number = |text: Option<&str>| text.and_then(|text| text.parse::<usize>().ok()).unwrap_or(1).saturating_sub(1);
+  BuildError {
+    entry: QuickfixEntry {
+      file: recent::display(file),
+      row: number(Some(line)),
+      column: number(column),
+      text: format!("{}: {}", severity.label(), text.trim())
+    },
+    severity,
+    message: text.trim().to_string()
+  }
+}
+
+// the errors and warnings in a build's output, in the forms that rustc
+// ("error: ..." then "--> file:line:col"), gcc and clang ("file:line:col: error: ...")
+// and Python (tracebacks, and pytest's "file.py:line: ...") report them
+pub fn parse(output: &[String]) -> Vec<BuildError> {
+  let rust_message = Regex::new(r"^(error|warning)(?:\[\w+\])?: (.+)$").unwrap();
+  let rust_location = Regex::new(r"^\s*--> (.+?):(\d+):(\d+)$").unwrap();
+  let gcc = Regex::new(r"^([^\s:][^:]*):(\d+):(?:(\d+):)? (fatal error|error|warning|note): (.+)$").unwrap();
+  let pytest = Regex::new(r"^(\S+\.py):(\d+): (.+)$").unwrap();
+  let python_frame = Regex::new(r#"^\s*File "(.+)", line (\d+)"#).unwrap();
+  let mut errors = Vec::new();
+  // a rustc message waiting for the location on the lines after it
+  let mut message: Option<(Severity, String)> = None;
+  // the innermost frame of a traceback, reported with the exception ending it
+  let mut frame: Option<(String, String)> = None;
+  for line in output {
+    if let Some(found) = rust_location.captures(line) {
+      if let Some((severity, text)) = message.take() {
+        errors.push(build_error(&found[1], &found[2], Some(&found[3]), severity, &text))
+      }
+    } else if let Some(found) = rust_message.captures(line) {
+      message = Some((severity(&found[1]), found[2].to_string()))
+    } else if let Some(found) = gcc.captures(line) {
+      errors.push(build_error(&found[1], &found[2], found.get(3).map(|column| column.as_str()), severity(&found[4]), &found[5]))
+    } else if let Some(found) = python_frame.captures(line) {
+      frame = Some((found[1].to_string(), found[2].to_string()))
+    } else if let Some(found) = pytest.captures(line) {
+      errors.push(build_error(&found[1], &found[2], None, Severity::Error, &found[3]))
+    } else if !line.is_empty() && !line.starts_with(char::is_whitespace) && !line.starts_with("Traceback") {
+      if let Some((file, row)) = frame.take() {
+        errors.push(build_error(&file, &row, None, Severity::Error, line))
+      }
+    }
+  }
+  errors
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use crate::motion::Position;
+
+const MAX_ENTRIES: usize = 100;
+
+// a position together with the file it belongs to
+pub type Location = (String, Position);
+
+// where 'row' ends up once 'range' has been replaced by 'new_len' rows,
+// None if it was one of the rows removed
+pub fn shift(row: usize, range: &Range<usize>, new_len: usize) -> Option<usize> {
+  if row < range.start {
+    Some(row)
+  } else if row >= range.end {
+    Some(row - range.len() + new_len)
+  } else if row - range.start < new_len {
+    Some(row)
+  } else {
+    None
+  }
+}
+
+// list positions survive their row being removed by moving past the edit
+fn shift_entry(position: &mut Position, range: &Range<usize>, new_len: usize) {
+  position.0 = shift(position.0, range, new_len).unwrap_or(range.start + new_len)
+}
+
+#[derive(Debug, Default)]
+struct ChangeList {
+  entries: Vec<Position>,
+  current: usize
+}
+
+// buffer-local marks (a-z) and change lists are kept per file name, global
+// marks (A-Z) and the jump list span every file that has been open
+#[derive(Debug, Default)]
+pub struct Marks {
+  local: HashMap<String, HashMap<char, Position>>,
+  global: HashMap<char, Location>,
+  // where the cursor was before the latest jump, the ' and ` marks
+  context: Option<Location>,
+  jumps: Vec<Location>,
+  jump_idx: usize,
+  changes: HashMap<String, ChangeList>
+}
+
+impl Marks {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn set(&mut self, file: &str, name: char, pos: Position) {
+    match name {
+      'a'..='z' => {
+        self.local.entry(file.to_string()).or_default().insert(name, pos);
+      },
+      'A'..='Z' => {
+        self.global.insert(name, (file.to_string(), pos));
+      },
+      '\'' | '`' => self.context = Some((file.to_string(), pos)),
+      _ => ()
+    }
+  }
+
+  pub fn get(&self, file: &str, name: char) -> Option<Location> {
+    match name {
+      'a'..='z' => self.local.get(file)?.get(&name).map(|pos| (file.to_string(), *pos)),
+      'A'..='Z' => self.global.get(&name).cloned(),
+      '\'' | '`' => self.context.clone(),
+      // the position of the last change
+      '.' => self.changes.get(file)?.entries.last().map(|pos| (file.to_string(), *pos)),
+      _ => None
+    }
+  }
+
+  // remembers 'pos' as the place a jump was made from
+  pub fn push_jump(&mut self, file: &str, pos: Position) {
+    self.jumps.retain(|(name, jump)| name != file || jump.0 != pos.0);
+    self.jumps.push((file.to_string(), pos));
+    if self.jumps.len() > MAX_ENTRIES {
+      self.jumps.remove(0);
+    }
+    self.jump_idx = self.jumps.len();
+    self.context = Some((file.to_string(), pos))
+  }
+
+  pub fn jump_older(&mut self, file: &str, pos: Position, count: usize) -> Option<Location> {
+    let target = self.jump_idx.checked_sub(count)?;
+    if self.jump_idx == self.jumps.len() {
+      // leaving the newest end, keep the current position so Ctrl-I can come back
+      self.push_jump(file, pos);
+      self.jump_idx = self.jumps.len() - 1;
+      return self.jump_older(file, pos, count)
+    }
+    self.jump_idx = target;
+    self.jumps.get(target).cloned()
+  }
+
+  pub fn jump_newer(&mut self, count: usize) -> Option<Location> {
+    let target = self.jump_idx + count;
+    if target >= self.jumps.len() {
+      return None
+    }
+    self.jump_idx = target;
+    self.jumps.get(target).cloned()
+  }
+
+  pub fn record_change(&mut self, file: &str, pos: Position) {
+    let list = self.changes.entry(file.to_string()).or_default();
+    // successive changes on one row count as a single entry
+    if list.entries.last().is_some_and(|last| last.0 == pos.0) {
+      list.entries.pop();
+    }
+    list.entries.push(pos);
+    if list.entries.len() > MAX_ENTRIES {
+      list.entries.remove(0);
+    }
+    list.current = list.entries.len()
+  }
+
+  pub fn change_older(&mut self, file: &str, count: usize) -> Option<Position> {
+    let list = self.changes.get_mut(file)?;
+    let target = list.current.checked_sub(count)?;
+    list.current = target;
+    list.entries.get(target).copied()
+  }
+
+  pub fn change_newer(&mut self, file: &str, count: usize) -> Option<Position> {
+    let list = self.changes.get_mut(file)?;
+    let target = list.current + count;
+    if target >= list.entries.len() {
+      return None
+    }
+    list.current = target;
+    list.entries.get(target).copied()
+  }
+
+  // follows 'range' of rows in 'file' being replaced by 'new_len' rows;
+  // marks on removed rows are deleted, list entries move past the edit
+  pub fn adjust(&mut self, file: &str, range: Range<usize>, new_len: usize) {
+    if let Some(local) = self.local.get_mut(file) {
+      local.retain(|_, pos| match shift(pos.0, &range, new_len) {
+        Some(row) => {
+          pos.0 = row;
+          true
+        },
+        None => false
+      });
+    }
+    self.global.retain(|_, (name, pos)| {
+      if name != file {
+        return true
+      }
+      match shift(pos.0, &range, new_len) {
+        Some(row) => {
+          pos.0 = row;
+          true
+        },
+        None => false
+      }
+    });
+    for (name, pos) in self.jumps.iter_mut().chain(self.context.iter_mut()) {
+      if name == file {
+        shift_entry(pos, &range, new_len)
+      }
+    }
+    if let Some(list) = self.changes.get_mut(file) {
+      for pos in list.entries.iter_mut() {
+        shift_entry(pos, &range, new_len)
+      }
+    }
+  }
+}
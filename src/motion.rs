@@ -0,0 +1,352 @@
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::file::Document;
+use crate::highlighting::{in_literal, Token};
+
+// positions are (row, column) pairs, columns counted in graphemes
+pub type Position = (usize, usize);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+  Left,
+  Right,
+  Up,
+  Down,
+  WordForward,
+  WordBackward,
+  WordEnd,
+  LineStart,
+  FirstNonBlank,
+  LineEnd,
+  FirstLine,
+  LastLine,
+  FindForward(char),
+  TillForward(char),
+  FindBackward(char),
+  TillBackward(char),
+  MatchPair,
+  // 'x goes to the mark's row, `x to its exact position; marks
+  // live in the editor, which resolves these itself
+  Mark(char),
+  MarkExact(char)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MotionKind {
+  Exclusive,
+  Inclusive,
+  Linewise
+}
+
+// the text an operator acts on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Span {
+  Chars(Position, Position),
+  Lines(Range<usize>)
+}
+
+impl Motion {
+  pub fn kind(&self) -> MotionKind {
+    match self {
+      Motion::Up |
+      Motion::Down |
+      Motion::FirstLine |
+      Motion::LastLine |
+      Motion::Mark(_) => MotionKind::Linewise,
+      Motion::WordEnd |
+      Motion::LineEnd |
+      Motion::FindForward(_) |
+      Motion::TillForward(_) |
+      Motion::MatchPair => MotionKind::Inclusive,
+      _ => MotionKind::Exclusive
+    }
+  }
+
+  // motions that leave an entry in the jump list
+  pub fn is_jump(&self) -> bool {
+    matches!(self,
+      Motion::FirstLine |
+      Motion::LastLine |
+      Motion::MatchPair |
+      Motion::Mark(_) |
+      Motion::MarkExact(_))
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CharClass {
+  Blank,
+  Punctuation,
+  Word
+}
+
+pub fn class_of(grapheme: &str) -> CharClass {
+  match grapheme.chars().next() {
+    Some(ch) if ch.is_whitespace() => CharClass::Blank,
+    Some(ch) if ch.is_alphanumeric() || ch == '_' => CharClass::Word,
+    Some(ch) if ch.is_ascii_punctuation() => CharClass::Punctuation,
+    Some(_) => CharClass::Word,
+    None => CharClass::Blank
+  }
+}
+
+pub fn graphemes(file: &Document, row: usize) -> Vec<&str> {
+  file.get_row(row)
+    .map(|row| row.content().graphemes(true).collect())
+    .unwrap_or_default()
+}
+
+fn row_len(file: &Document, row: usize) -> usize {
+  file.get_row(row).map(|row| row.len()).unwrap_or(0)
+}
+
+// the end of a row counts as a blank, so words never continue across lines
+fn class_at(file: &Document, pos: Position) -> CharClass {
+  graphemes(file, pos.0).get(pos.1).map(|g| class_of(g)).unwrap_or(CharClass::Blank)
+}
+
+fn is_empty_row(file: &Document, row: usize) -> bool {
+  row_len(file, row) == 0
+}
+
+// steps through every column of the document including each row's end
+pub fn next_position(file: &Document, pos: Position) -> Option<Position> {
+  if pos.1 < row_len(file, pos.0) {
+    Some((pos.0, pos.1 + 1))
+  } else if pos.0 + 1 < file.len() {
+    Some((pos.0 + 1, 0))
+  } else {
+    None
+  }
+}
+
+pub fn prev_position(file: &Document, pos: Position) -> Option<Position> {
+  if pos.1 > 0 {
+    Some((pos.0, pos.1 - 1))
+  } else if pos.0 > 0 {
+    Some((pos.0 - 1, row_len(file, pos.0 - 1)))
+  } else {
+    None
+  }
+}
+
+fn word_forward(file: &Document, pos: Position) -> Position {
+  let start_class = class_at(file, pos);
+  let mut current = pos;
+  if start_class != CharClass::Blank {
+    while class_at(file, current) == start_class {
+      match next_position(file, current) {
+        Some(next) => current = next,
+        None => return (current.0, row_len(file, current.0))
+      }
+    }
+  }
+  while class_at(file, current) == CharClass::Blank {
+    // an empty line counts as a word of its own
+    if current != pos && current.1 == 0 && is_empty_row(file, current.0) {
+      break
+    }
+    match next_position(file, current) {
+      Some(next) => current = next,
+      None => break
+    }
+  }
+  current
+}
+
+fn word_end(file: &Document, pos: Position) -> Position {
+  let mut current = match next_position(file, pos) {
+    Some(next) => next,
+    None => return pos
+  };
+  while class_at(file, current) == CharClass::Blank {
+    match next_position(file, current) {
+      Some(next) => current = next,
+      None => return current
+    }
+  }
+  let class = class_at(file, current);
+  while let Some(next) = next_position(file, current) {
+    if class_at(file, next) != class || next.0 != current.0 {
+      break
+    }
+    current = next
+  }
+  current
+}
+
+fn word_backward(file: &Document, pos: Position) -> Position {
+  let mut current = match prev_position(file, pos) {
+    Some(prev) => prev,
+    None => return pos
+  };
+  while class_at(file, current) == CharClass::Blank {
+    if current.1 == 0 && is_empty_row(file, current.0) {
+      return current
+    }
+    match prev_position(file, current) {
+      Some(prev) => current = prev,
+      None => return current
+    }
+  }
+  let class = class_at(file, current);
+  while current.1 > 0 && class_at(file, (current.0, current.1 - 1)) == class {
+    current.1 -= 1
+  }
+  current
+}
+
+pub fn first_non_blank(file: &Document, row: usize) -> usize {
+  graphemes(file, row)
+    .iter()
+    .position(|g| class_of(g) != CharClass::Blank)
+    .unwrap_or_else(|| row_len(file, row))
+}
+
+fn find_in_row(file: &Document, pos: Position, target: char, forward: bool, count: usize) -> Option<usize> {
+  let row = graphemes(file, pos.0);
+  let target = target.to_string();
+  let columns: Box<dyn Iterator<Item = usize>> = if forward {
+    Box::new((pos.1 + 1)..row.len())
+  } else {
+    Box::new((0..pos.1.min(row.len())).rev())
+  };
+  columns
+    .filter(|column| row[*column] == target)
+    .nth(count - 1)
+}
+
+fn pair_of(bracket: &str) -> Option<(&'static str, &'static str, bool)> {
+  match bracket {
+    "(" => Some(("(", ")", true)),
+    "[" => Some(("[", "]", true)),
+    "{" => Some(("{", "}", true)),
+    ")" => Some(("(", ")", false)),
+    "]" => Some(("[", "]", false)),
+    "}" => Some(("{", "}", false)),
+    _ => None
+  }
+}
+
+// the graphemes of a row, those in strings, chars and comments left empty
+// so they are never taken for brackets
+pub(crate) fn code_graphemes<'a>(file: &'a Document, tokens: &Option<Vec<Vec<Token>>>, row: usize) -> Vec<&'a str> {
+  file.get_row(row)
+    .map(|line| line.content()
+      .grapheme_indices(true)
+      .map(|(idx, grapheme)| match tokens {
+        Some(tokens) if in_literal(&tokens[row], idx) => "",
+        _ => grapheme
+      })
+      .collect())
+    .unwrap_or_default()
+}
+
+// the bracket matching the first bracket at or after the cursor on its row,
+// through as many rows as it takes; brackets in strings, chars and comments
+// don't count
+pub fn match_pair(file: &Document, pos: Position) -> Option<Position> {
+  let tokens = file.tokens();
+  let row = code_graphemes(file, &tokens, pos.0);
+  let start = (pos.1..row.len()).find(|column| pair_of(row[*column]).is_some())?;
+  match_from(file, &tokens, (pos.0, start))
+}
+
+// the bracket matching the one under the cursor, None when it is on none
+pub fn matching_bracket(file: &Document, pos: Position) -> Option<Position> {
+  pair_of(graphemes(file, pos.0).get(pos.1)?)?;
+  let tokens = file.tokens();
+  pair_of(code_graphemes(file, &tokens, pos.0)[pos.1])?;
+  match_from(file, &tokens, pos)
+}
+
+fn match_from(file: &Document, tokens: &Option<Vec<Vec<Token>>>, start: Position) -> Option<Position> {
+  let (open, close, forward) = pair_of(code_graphemes(file, tokens, start.0)[start.1])?;
+  let (step_in, step_out) = if forward { (open, close) } else { (close, open) };
+  let mut depth = 0;
+  let rows: Box<dyn Iterator<Item = usize>> = if forward {
+    Box::new(start.0..file.len())
+  } else {
+    Box::new((0..=start.0).rev())
+  };
+  for row_no in rows {
+    let row = code_graphemes(file, tokens, row_no);
+    let columns: Box<dyn Iterator<Item = usize>> = match (forward, row_no == start.0) {
+      (true, true) => Box::new(start.1..row.len()),
+      (true, false) => Box::new(0..row.len()),
+      (false, true) => Box::new((0..=start.1).rev()),
+      (false, false) => Box::new((0..row.len()).rev())
+    };
+    for column in columns {
+      if row[column] == step_in {
+        depth += 1
+      } else if row[column] == step_out {
+        depth -= 1;
+        if depth == 0 {
+          return Some((row_no, column))
+        }
+      }
+    }
+  }
+  None
+}
+
+// where a motion repeated 'count' times takes the cursor, if anywhere
+pub fn target(file: &Document, pos: Position, motion: Motion, count: Option<usize>) -> Option<Position> {
+  let times = count.unwrap_or(1);
+  let last_row = file.len() - 1;
+  let clamp = |row: usize, column: usize| (row, column.min(row_len(file, row)));
+  Some(match motion {
+    Motion::Left => (pos.0, pos.1.saturating_sub(times)),
+    Motion::Right => clamp(pos.0, pos.1 + times),
+    Motion::Up => clamp(pos.0.saturating_sub(times), pos.1),
+    Motion::Down => clamp((pos.0 + times).min(last_row), pos.1),
+    Motion::WordForward => (0..times).fold(pos, |current, _| word_forward(file, current)),
+    Motion::WordBackward => (0..times).fold(pos, |current, _| word_backward(file, current)),
+    Motion::WordEnd => (0..times).fold(pos, |current, _| word_end(file, current)),
+    Motion::LineStart => (pos.0, 0),
+    Motion::FirstNonBlank => (pos.0, first_non_blank(file, pos.0)),
+    Motion::LineEnd => {
+      let row = (pos.0 + times - 1).min(last_row);
+      (row, row_len(file, row).saturating_sub(1))
+    },
+    Motion::FirstLine => {
+      let row = count.unwrap_or(1).saturating_sub(1).min(last_row);
+      (row, first_non_blank(file, row))
+    },
+    Motion::LastLine => {
+      let row = count.map(|line| line.saturating_sub(1)).unwrap_or(last_row).min(last_row);
+      (row, first_non_blank(file, row))
+    },
+    Motion::FindForward(ch) => (pos.0, find_in_row(file, pos, ch, true, times)?),
+    Motion::TillForward(ch) => (pos.0, find_in_row(file, pos, ch, true, times)? - 1),
+    Motion::FindBackward(ch) => (pos.0, find_in_row(file, pos, ch, false, times)?),
+    Motion::TillBackward(ch) => (pos.0, find_in_row(file, pos, ch, false, times)? + 1),
+    Motion::MatchPair => match_pair(file, pos)?,
+    Motion::Mark(_) | Motion::MarkExact(_) => return None
+  })
+}
+
+// the span an operator covers when combined with a motion
+pub fn span(file: &Document, pos: Position, motion: Motion, count: Option<usize>) -> Option<Span> {
+  Some(span_to(file, pos, target(file, pos, motion, count)?, motion))
+}
+
+// the span between the cursor and where 'motion' took it
+pub fn span_to(file: &Document, pos: Position, target: Position, motion: Motion) -> Span {
+  let (start, end) = if target < pos { (target, pos) } else { (pos, target) };
+  match motion.kind() {
+    MotionKind::Linewise => Span::Lines(start.0..(end.0 + 1)),
+    MotionKind::Inclusive => Span::Chars(start, (end.0, (end.1 + 1).min(row_len(file, end.0)))),
+    MotionKind::Exclusive => {
+      // an exclusive motion ending at the start of a later row stops at the previous
+      // row's end, and 'w' never carries an operator onto the next row's indent
+      let at_row_start = end.1 == 0 || (motion == Motion::WordForward && end.1 <= first_non_blank(file, end.0));
+      if end.0 > start.0 && at_row_start {
+        Span::Chars(start, (end.0 - 1, row_len(file, end.0 - 1)))
+      } else {
+        Span::Chars(start, end)
+      }
+    }
+  }
+}
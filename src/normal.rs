@@ -0,0 +1,313 @@
+use crate::motion::Motion;
+use crate::textobject::TextObject;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+  Delete,
+  Change,
+  Yank,
+  Indent,
+  Dedent,
+  // = reindents lines as the language's rules say
+  Reindent,
+  Lowercase,
+  Uppercase
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+  Motion(Motion),
+  Object(TextObject, bool),
+  // the operator doubled, as in dd or >>
+  Lines
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InsertAt {
+  Cursor,
+  After,
+  LineStart,
+  LineEnd,
+  LineBelow,
+  LineAbove
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+  Move(Motion),
+  Operate(Operator, Target),
+  Insert(InsertAt),
+  Put(bool),
+  Undo,
+  Repeat,
+  Record(char),
+  Replay(char),
+  SetMark(char),
+  JumpOlder,
+  JumpNewer,
+  ChangeOlder,
+  ChangeNewer,
+  // n or N, true for N which searches in the opposite direction
+  SearchNext(bool),
+  // * or #, true for # which searches backward
+  SearchWord(bool),
+  // opens the command line with ':' or a search prompt, '/' or '?'
+  CommandLine(char),
+  // K, gd and gr, answered by the language server
+  Hover,
+  GotoDefinition,
+  References,
+  // ]d or [d, true for [d which goes to the previous diagnostic
+  NextDiagnostic(bool)
+}
+
+impl Action {
+  // whether the action alters the document and so can be repeated with '.'
+  pub fn is_change(&self) -> bool {
+    match self {
+      Action::Operate(op, _) => *op != Operator::Yank,
+      Action::Insert(_) | Action::Put(_) => true,
+      _ => false
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormalCommand {
+  pub register: Option<char>,
+  pub count: Option<usize>,
+  pub action: Action
+}
+
+impl NormalCommand {
+  pub fn new(count: Option<usize>, action: Action) -> Self {
+    Self {
+      register: None,
+      count,
+      action
+    }
+  }
+}
+
+// a key typed during an Insert session, replayed by '.'
+#[derive(Debug, Clone, PartialEq)]
+pub enum InsertKey {
+  Char(char),
+  Tab,
+  Newline,
+  Backspace,
+  Text(String)
+}
+
+// the last change: the command that made it plus what was typed afterwards
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+  pub command: NormalCommand,
+  pub inserted: Vec<InsertKey>,
+  // how many times 'inserted' goes in: the count of i, a, o and the like
+  pub count: usize
+}
+
+impl Change {
+  pub fn new(command: NormalCommand) -> Self {
+    let count = match command.action {
+      Action::Insert(_) => command.count.unwrap_or(1),
+      _ => 1
+    };
+    Self { command, inserted: Vec::new(), count }
+  }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Parsed {
+  Pending,
+  Invalid,
+  Complete(NormalCommand)
+}
+
+// reads an optional count off the front of the keys
+fn count(keys: &[char]) -> (Option<usize>, &[char]) {
+  let digits = keys
+    .iter()
+    .enumerate()
+    .take_while(|(idx, key)| key.is_ascii_digit() && (*idx > 0 || **key != '0'))
+    .count();
+  if digits == 0 {
+    return (None, keys)
+  }
+  let value = keys[..digits].iter().collect::<String>().parse().ok();
+  (value, &keys[digits..])
+}
+
+// Some(Ok) for a motion, Some(Err) while more keys are needed, None if it is none
+fn motion(keys: &[char]) -> Option<Result<(Motion, usize), ()>> {
+  let first = *keys.first()?;
+  let simple = match first {
+    'h' => Some(Motion::Left),
+    'l' | ' ' => Some(Motion::Right),
+    'k' => Some(Motion::Up),
+    'j' => Some(Motion::Down),
+    'w' => Some(Motion::WordForward),
+    'b' => Some(Motion::WordBackward),
+    'e' => Some(Motion::WordEnd),
+    '0' => Some(Motion::LineStart),
+    '^' => Some(Motion::FirstNonBlank),
+    '$' => Some(Motion::LineEnd),
+    'G' => Some(Motion::LastLine),
+    '%' => Some(Motion::MatchPair),
+    _ => None
+  };
+  if let Some(simple) = simple {
+    return Some(Ok((simple, 1)))
+  }
+  match first {
+    '\'' | '`' => Some(match keys.get(1) {
+      Some(name) if first == '\'' => Ok((Motion::Mark(*name), 2)),
+      Some(name) => Ok((Motion::MarkExact(*name), 2)),
+      None => Err(())
+    }),
+    'f' | 't' | 'F' | 'T' => Some(match keys.get(1) {
+      Some(ch) => Ok((match first {
+        'f' => Motion::FindForward(*ch),
+        't' => Motion::TillForward(*ch),
+        'F' => Motion::FindBackward(*ch),
+        _ => Motion::TillBackward(*ch)
+      }, 2)),
+      None => Err(())
+    }),
+    'g' => match keys.get(1) {
+      Some('g') => Some(Ok((Motion::FirstLine, 2))),
+      Some(_) => None,
+      None => Some(Err(()))
+    },
+    _ => None
+  }
+}
+
+fn operator(keys: &[char]) -> Option<Result<(Operator, usize), ()>> {
+  Some(match keys.first()? {
+    'd' => Ok((Operator::Delete, 1)),
+    'c' => Ok((Operator::Change, 1)),
+    'y' => Ok((Operator::Yank, 1)),
+    '>' => Ok((Operator::Indent, 1)),
+    '<' => Ok((Operator::Dedent, 1)),
+    '=' => Ok((Operator::Reindent, 1)),
+    'g' => match keys.get(1) {
+      Some('u') => Ok((Operator::Lowercase, 2)),
+      Some('U') => Ok((Operator::Uppercase, 2)),
+      Some(_) => return None,
+      None => Err(())
+    },
+    _ => return None
+  })
+}
+
+fn complete(register: Option<char>, count: Option<usize>, action: Action) -> Parsed {
+  Parsed::Complete(NormalCommand {
+    register,
+    count,
+    action
+  })
+}
+
+fn multiply(first: Option<usize>, second: Option<usize>) -> Option<usize> {
+  match (first, second) {
+    (Some(first), Some(second)) => Some(first * second),
+    (first, None) => first,
+    (None, second) => second
+  }
+}
+
+// parses the keys typed so far in Normal mode:
+// ["x] [count] (motion | action | operator [count] (motion | text object | operator))
+pub fn parse(keys: &[char]) -> Parsed {
+  let (register, keys) = match keys {
+    ['"'] => return Parsed::Pending,
+    ['"', name, rest @ ..] => (Some(*name), rest),
+    _ => (None, keys)
+  };
+  let (count_before, keys) = count(keys);
+  let first = match keys.first() {
+    Some(first) => *first,
+    None => return Parsed::Pending
+  };
+  // q and @ take a register name, m a mark name
+  match keys {
+    ['q'] | ['@'] | ['m'] | [']'] | ['['] => return Parsed::Pending,
+    ['q', name] => return complete(register, count_before, Action::Record(*name)),
+    ['@', name] => return complete(register, count_before, Action::Replay(*name)),
+    ['m', name] => return complete(register, count_before, Action::SetMark(*name)),
+    ['g', ';'] => return complete(register, count_before, Action::ChangeOlder),
+    ['g', ','] => return complete(register, count_before, Action::ChangeNewer),
+    ['g', 'd'] => return complete(register, count_before, Action::GotoDefinition),
+    ['g', 'r'] => return complete(register, count_before, Action::References),
+    [']', 'd'] => return complete(register, count_before, Action::NextDiagnostic(false)),
+    ['[', 'd'] => return complete(register, count_before, Action::NextDiagnostic(true)),
+    _ => ()
+  }
+  let action = match first {
+    'i' => Some(Action::Insert(InsertAt::Cursor)),
+    'a' => Some(Action::Insert(InsertAt::After)),
+    'I' => Some(Action::Insert(InsertAt::LineStart)),
+    'A' => Some(Action::Insert(InsertAt::LineEnd)),
+    'o' => Some(Action::Insert(InsertAt::LineBelow)),
+    'O' => Some(Action::Insert(InsertAt::LineAbove)),
+    'p' => Some(Action::Put(true)),
+    'P' => Some(Action::Put(false)),
+    'u' => Some(Action::Undo),
+    '.' => Some(Action::Repeat),
+    ':' | '/' | '?' => Some(Action::CommandLine(first)),
+    'n' => Some(Action::SearchNext(false)),
+    'N' => Some(Action::SearchNext(true)),
+    '*' => Some(Action::SearchWord(false)),
+    '#' => Some(Action::SearchWord(true)),
+    'K' => Some(Action::Hover),
+    'x' => Some(Action::Operate(Operator::Delete, Target::Motion(Motion::Right))),
+    'X' => Some(Action::Operate(Operator::Delete, Target::Motion(Motion::Left))),
+    'D' => Some(Action::Operate(Operator::Delete, Target::Motion(Motion::LineEnd))),
+    'C' => Some(Action::Operate(Operator::Change, Target::Motion(Motion::LineEnd))),
+    's' => Some(Action::Operate(Operator::Change, Target::Motion(Motion::Right))),
+    'Y' => Some(Action::Operate(Operator::Yank, Target::Lines)),
+    _ => None
+  };
+  if let Some(action) = action {
+    return if keys.len() == 1 { complete(register, count_before, action) } else { Parsed::Invalid }
+  }
+  match motion(keys) {
+    Some(Ok((motion, used))) if used == keys.len() => return complete(register, count_before, Action::Move(motion)),
+    Some(Err(())) => return Parsed::Pending,
+    _ => ()
+  }
+  let (op, used) = match operator(keys) {
+    Some(Ok(op)) => op,
+    Some(Err(())) => return Parsed::Pending,
+    None => return Parsed::Invalid
+  };
+  let op_keys = &keys[..used];
+  let (count_after, keys) = count(&keys[used..]);
+  let count = multiply(count_before, count_after);
+  if keys.is_empty() {
+    return Parsed::Pending
+  }
+  // doubling the operator (or its last key, as in guu) works on whole lines
+  if keys == op_keys || (op_keys.len() == 2 && keys == &op_keys[1..]) {
+    return complete(register, count, Action::Operate(op, Target::Lines))
+  }
+  if op_keys.len() == 2 && keys.len() < 2 && keys[0] == op_keys[0] {
+    return Parsed::Pending
+  }
+  match keys[0] {
+    'i' | 'a' => match keys.get(1) {
+      Some(key) => match TextObject::from_key(*key) {
+        Some(object) if keys.len() == 2 => complete(register, count, Action::Operate(op, Target::Object(object, keys[0] == 'i'))),
+        _ => Parsed::Invalid
+      },
+      None => Parsed::Pending
+    },
+    _ => match motion(keys) {
+      Some(Ok((motion, used))) if used == keys.len() => complete(register, count, Action::Operate(op, Target::Motion(motion))),
+      Some(Err(())) => Parsed::Pending,
+      _ => Parsed::Invalid
+    }
+  }
+}
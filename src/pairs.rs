@@ -0,0 +1,35 @@
+use crate::highlighting::{JsonValue, Language};
+
+// the brackets and quotes typed in pairs in a language, unless its syntax
+// file lists others under "pairs", such as ["()", "[]", "<>"]
+pub fn pairs(language: Option<Language>, syntax_file: Option<&JsonValue>) -> Vec<(char, char)> {
+  if let Some(configured) = syntax_file.map(|syntax| &syntax["pairs"]).filter(|pairs| pairs.is_array()) {
+    return configured
+      .members()
+      .filter_map(|pair| {
+        let mut chars = pair.as_str()?.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+          (Some(open), Some(close), None) => Some((open, close)),
+          _ => None
+        }
+      })
+      .collect()
+  }
+  let mut pairs = vec![('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')];
+  // a quote in Rust is as often a lifetime as a char
+  if matches!(language, Some(Language::Python) | Some(Language::C)) {
+    pairs.push(('\'', '\''))
+  }
+  pairs
+}
+
+// whether a quote typed after 'before' (the row up to the cursor) starts a
+// string: not after a word, unless the word is a prefix such as f or br
+pub fn opens_string(before: &str) -> bool {
+  let word: String = before
+    .chars()
+    .rev()
+    .take_while(|ch| ch.is_alphanumeric() || *ch == '_')
+    .collect();
+  word.is_empty() || matches!(word.to_lowercase().as_str(), "f" | "r" | "b" | "u" | "rb" | "br" | "fr" | "rf")
+}
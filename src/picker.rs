@@ -0,0 +1,223 @@
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use ignore::WalkBuilder;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PickerKind {
+  Files,
+  // files opened during the session
+  Buffers,
+  // ex commands, each with what it does
+  Commands,
+  // files opened in any session
+  Recent,
+  // functions defined in the open file
+  Symbols,
+  // what the language server suggests at the cursor in Insert mode
+  Completion
+}
+
+impl PickerKind {
+  pub fn title(&self) -> &'static str {
+    match self {
+      PickerKind::Files => "Files",
+      PickerKind::Buffers => "Buffers",
+      PickerKind::Commands => "Commands",
+      PickerKind::Recent => "Recent files",
+      PickerKind::Symbols => "Symbols",
+      PickerKind::Completion => "Completions"
+    }
+  }
+
+  pub fn has_preview(&self) -> bool {
+    !matches!(self, PickerKind::Commands | PickerKind::Completion)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickerItem {
+  // what is shown and matched against the query
+  pub label: String,
+  // shown dimmed after the label
+  pub detail: String,
+  // what accepting the item acts on, such as a path
+  pub value: String
+}
+
+impl PickerItem {
+  pub fn new(label: &str, detail: &str, value: &str) -> Self {
+    Self {
+      label: label.to_string(),
+      detail: detail.to_string(),
+      value: value.to_string()
+    }
+  }
+}
+
+// an item that matched the query: its index, score and the matched characters
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickerMatch {
+  pub item: usize,
+  pub score: i64,
+  pub positions: Vec<usize>
+}
+
+// a filterable list of items shown over the editor, possibly still being
+// filled from a background thread
+#[derive(Debug)]
+pub struct Picker {
+  pub kind: PickerKind,
+  pub query: String,
+  items: Vec<PickerItem>,
+  pub matches: Vec<PickerMatch>,
+  pub selected: usize,
+  source: Option<Receiver<PickerItem>>
+}
+
+fn is_boundary(ch: char) -> bool {
+  matches!(ch, '/' | '\\' | '_' | '-' | '.' | ' ' | ':')
+}
+
+// scores 'candidate' when 'query' is a subsequence of it (ignoring case), also
+// returning which characters matched; runs of characters, matches at word starts
+// and matches in the last path component score higher, skipped characters lower
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+  let chars: Vec<char> = candidate.chars().collect();
+  let name_start = candidate.rfind('/').map(|idx| candidate[..=idx].chars().count()).unwrap_or(0);
+  let mut positions: Vec<usize> = Vec::new();
+  let mut score = 0;
+  let mut from = 0;
+  for wanted in query.chars() {
+    let idx = (from..chars.len()).find(|idx| chars[*idx].to_lowercase().eq(wanted.to_lowercase()))?;
+    score += 1;
+    if idx > 0 && positions.last() == Some(&(idx - 1)) {
+      score += 5
+    }
+    if idx == 0 || is_boundary(chars[idx - 1]) {
+      score += 8
+    } else if chars[idx - 1].is_lowercase() && chars[idx].is_uppercase() {
+      score += 6
+    }
+    if idx >= name_start {
+      score += 3
+    }
+    score -= std::cmp::min(idx - from, 5) as i64;
+    positions.push(idx);
+    from = idx + 1
+  }
+  score -= chars.len() as i64 / 10;
+  Some((score, positions))
+}
+
+// every file under 'root' that .gitignore does not exclude, found on another thread
+pub fn index_files(root: &str) -> Receiver<PickerItem> {
+  let (sender, receiver) = channel();
+  let root = root.to_string();
+  std::thread::spawn(move || {
+    let walk = WalkBuilder::new(&root).require_git(false).build();
+    for entry in walk.flatten() {
+      if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+        continue
+      }
+      let path = entry.path();
+      let path = path.strip_prefix("./").unwrap_or(path).to_string_lossy().to_string();
+      // the picker was closed, nobody is listening anymore
+      if sender.send(PickerItem::new(&path, "", &path)).is_err() {
+        return
+      }
+    }
+  });
+  receiver
+}
+
+impl Picker {
+  pub fn new(kind: PickerKind, items: Vec<PickerItem>) -> Self {
+    Self {
+      kind,
+      query: String::new(),
+      items,
+      matches: Vec::new(),
+      selected: 0,
+      source: None
+    }
+  }
+
+  pub fn with_source(kind: PickerKind, source: Receiver<PickerItem>) -> Self {
+    Self {
+      source: Some(source),
+      ..Self::new(kind, Vec::new())
+    }
+  }
+
+  pub fn is_loading(&self) -> bool {
+    self.source.is_some()
+  }
+
+  pub fn len(&self) -> usize {
+    self.items.len()
+  }
+
+  // takes whatever the background source has sent so far, true if anything arrived
+  pub fn receive(&mut self) -> bool {
+    let mut received = false;
+    while let Some(source) = &self.source {
+      match source.try_recv() {
+        Ok(item) => {
+          self.items.push(item);
+          received = true
+        },
+        Err(TryRecvError::Empty) => break,
+        Err(TryRecvError::Disconnected) => self.source = None
+      }
+    }
+    received
+  }
+
+  // ranks the items against the query, 'bonus' adds to an item's score
+  // (such as for files opened recently); without a query the order is kept
+  pub fn refilter<F: Fn(&PickerItem) -> i64>(&mut self, bonus: F) {
+    let mut matches: Vec<PickerMatch> = self.items
+      .iter()
+      .enumerate()
+      .filter_map(|(idx, item)| {
+        let (score, positions) = if self.query.is_empty() {
+          (0, Vec::new())
+        } else {
+          fuzzy_score(&self.query, &item.label)?
+        };
+        Some(PickerMatch {
+          item: idx,
+          score: score + bonus(item),
+          positions
+        })
+      })
+      .collect();
+    let by_length = !self.query.is_empty();
+    matches.sort_by(|first, second| second.score.cmp(&first.score)
+      .then(if by_length {
+        self.items[first.item].label.len().cmp(&self.items[second.item].label.len())
+      } else {
+        std::cmp::Ordering::Equal
+      })
+      .then(first.item.cmp(&second.item)));
+    self.matches = matches;
+    self.selected = std::cmp::min(self.selected, self.matches.len().saturating_sub(1))
+  }
+
+  pub fn item(&self, found: &PickerMatch) -> &PickerItem {
+    &self.items[found.item]
+  }
+
+  pub fn selected_item(&self) -> Option<&PickerItem> {
+    self.matches.get(self.selected).map(|found| self.item(found))
+  }
+
+  pub fn move_selection(&mut self, down: bool) {
+    if down {
+      if self.selected + 1 < self.matches.len() {
+        self.selected += 1
+      }
+    } else {
+      self.selected = self.selected.saturating_sub(1)
+    }
+  }
+}
@@ -0,0 +1,55 @@
+// a location in some file with the text found there, one line of a quickfix list
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuickfixEntry {
+  pub file: String,
+  pub row: usize,
+  pub column: usize,
+  pub text: String
+}
+
+impl QuickfixEntry {
+  // how the entry reads in the list buffer, file:line:column: text
+  pub fn describe(&self) -> String {
+    format!("{}:{}:{}: {}", self.file, self.row + 1, self.column + 1, self.text)
+  }
+}
+
+// results of the last :grep, listed in a buffer of their own
+#[derive(Debug, Default)]
+pub struct Quickfix {
+  pub entries: Vec<QuickfixEntry>,
+  pub current: usize
+}
+
+impl Quickfix {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn set(&mut self, entries: Vec<QuickfixEntry>) {
+    self.entries = entries;
+    self.current = 0
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  pub fn select(&mut self, idx: usize) -> Option<&QuickfixEntry> {
+    let entry = self.entries.get(idx)?;
+    self.current = idx;
+    Some(entry)
+  }
+
+  pub fn next(&mut self, count: usize) -> Option<&QuickfixEntry> {
+    self.select(self.current + count)
+  }
+
+  pub fn prev(&mut self, count: usize) -> Option<&QuickfixEntry> {
+    self.select(self.current.checked_sub(count)?)
+  }
+
+  pub fn lines(&self) -> Vec<String> {
+    self.entries.iter().map(|entry| entry.describe()).collect()
+  }
+}
@@ -0,0 +1,56 @@
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+use dirs::home_dir;
+
+const MAX_FILES: usize = 100;
+
+// files opened in earlier sessions are kept in ~/.editrc/recent as absolute
+// paths, the latest first
+fn recent_file() -> Option<PathBuf> {
+  Some(home_dir()?.join(".editrc/recent"))
+}
+
+pub fn load() -> Vec<String> {
+  recent_file()
+    .and_then(|path| read_to_string(path).ok())
+    .map(|content| content.lines().filter(|line| !line.is_empty()).map(String::from).collect())
+    .unwrap_or_default()
+}
+
+// moves 'file_name' to the front of 'files'
+pub fn remember(files: &mut Vec<String>, file_name: &str) {
+  let path = match Path::new(file_name).canonicalize() {
+    Ok(path) => path.to_string_lossy().to_string(),
+    Err(_) => return
+  };
+  files.retain(|file| *file != path);
+  files.insert(0, path);
+  files.truncate(MAX_FILES)
+}
+
+// writes 'files' ahead of whatever other sessions have written meanwhile
+pub fn save(files: &[String]) -> std::io::Result<()> {
+  let path = match recent_file() {
+    Some(path) => path,
+    None => return Ok(())
+  };
+  let mut merged = files.to_vec();
+  for file in load() {
+    if !merged.contains(&file) {
+      merged.push(file)
+    }
+  }
+  merged.truncate(MAX_FILES);
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir)?
+  }
+  write(path, merged.join("\n") + "\n")
+}
+
+// how a recent file is shown: relative to the working directory when inside it
+pub fn display(file: &str) -> String {
+  std::env::current_dir()
+    .ok()
+    .and_then(|dir| Path::new(file).strip_prefix(dir).ok().map(|path| path.to_string_lossy().to_string()))
+    .unwrap_or_else(|| file.to_string())
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegisterContent {
+  Charwise(String),
+  Linewise(Vec<String>)
+}
+
+impl RegisterContent {
+  pub fn is_linewise(&self) -> bool {
+    matches!(self, RegisterContent::Linewise(_))
+  }
+
+  // the content as it would be written into a file
+  pub fn to_text(&self) -> String {
+    match self {
+      RegisterContent::Charwise(text) => text.clone(),
+      RegisterContent::Linewise(lines) => {
+        let mut text = lines.join("\n");
+        text.push('\n');
+        text
+      }
+    }
+  }
+
+  fn append(&mut self, other: RegisterContent) {
+    *self = match (std::mem::replace(self, RegisterContent::Charwise(String::new())), other) {
+      (RegisterContent::Charwise(mut text), RegisterContent::Charwise(other)) => {
+        text.push_str(&other);
+        RegisterContent::Charwise(text)
+      },
+      (RegisterContent::Linewise(mut lines), RegisterContent::Linewise(mut other)) => {
+        lines.append(&mut other);
+        RegisterContent::Linewise(lines)
+      },
+      (RegisterContent::Linewise(mut lines), RegisterContent::Charwise(other)) => {
+        lines.extend(other.split('\n').map(String::from));
+        RegisterContent::Linewise(lines)
+      },
+      (RegisterContent::Charwise(text), RegisterContent::Linewise(mut other)) => {
+        let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+        lines.append(&mut other);
+        RegisterContent::Linewise(lines)
+      }
+    }
+  }
+}
+
+// register names follow vim:
+// '"' unnamed, 'a'-'z' named ('A'-'Z' append), '0' last yank,
+// '1'-'9' delete history, '-' small delete, '_' black hole,
+// '+' and '*' the system clipboard (kept in sync by the editor)
+#[derive(Debug)]
+pub struct Registers {
+  unnamed: Option<RegisterContent>,
+  named: HashMap<char, RegisterContent>,
+  yank: Option<RegisterContent>,
+  numbered: [Option<RegisterContent>; 9],
+  small_delete: Option<RegisterContent>,
+  clipboard: Option<RegisterContent>
+}
+
+impl Registers {
+  pub fn new() -> Self {
+    Self {
+      unnamed: None,
+      named: HashMap::new(),
+      yank: None,
+      numbered: Default::default(),
+      small_delete: None,
+      clipboard: None
+    }
+  }
+
+  pub fn is_valid(name: char) -> bool {
+    matches!(name, '"' | '-' | '_' | '+' | '*' | '0'..='9' | 'a'..='z' | 'A'..='Z')
+  }
+
+  pub fn is_clipboard(name: char) -> bool {
+    name == '+' || name == '*'
+  }
+
+  pub fn get(&self, name: char) -> Option<&RegisterContent> {
+    match name {
+      '"' => self.unnamed.as_ref(),
+      '0' => self.yank.as_ref(),
+      '1'..='9' => self.numbered[name as usize - '1' as usize].as_ref(),
+      '-' => self.small_delete.as_ref(),
+      '+' | '*' => self.clipboard.as_ref(),
+      'a'..='z' | 'A'..='Z' => self.named.get(&name.to_ascii_lowercase()),
+      _ => None
+    }
+  }
+
+  pub fn yank(&mut self, name: Option<char>, content: RegisterContent) {
+    match name {
+      Some('_') => return,
+      Some(name) if name.is_ascii_alphabetic() => {
+        self.set_named(name, content);
+        self.unnamed = self.get(name).cloned();
+        return
+      },
+      Some(name) if Self::is_clipboard(name) => {
+        self.clipboard = Some(content.clone());
+        self.unnamed = Some(content);
+        return
+      },
+      _ => ()
+    }
+    self.yank = Some(content.clone());
+    self.unnamed = Some(content)
+  }
+
+  pub fn delete(&mut self, name: Option<char>, content: RegisterContent) {
+    match name {
+      Some('_') => return,
+      Some(name) if name.is_ascii_alphabetic() => {
+        self.set_named(name, content.clone());
+        self.unnamed = self.get(name).cloned()
+      },
+      Some(name) if Self::is_clipboard(name) => {
+        self.clipboard = Some(content.clone());
+        self.unnamed = Some(content.clone())
+      },
+      _ => self.unnamed = Some(content.clone())
+    }
+    // only deletes of at least one line rotate the numbered history
+    if content.is_linewise() || content.to_text().contains('\n') {
+      self.numbered.rotate_right(1);
+      self.numbered[0] = Some(content)
+    } else if name.is_none() {
+      self.small_delete = Some(content)
+    }
+  }
+
+  // stores a recorded macro, leaving the unnamed register alone
+  pub fn record(&mut self, name: char, keys: String) {
+    if name.is_ascii_alphabetic() {
+      self.set_named(name, RegisterContent::Charwise(keys))
+    }
+  }
+
+  // refreshes the clipboard register from the system clipboard
+  pub fn set_clipboard(&mut self, text: String) {
+    self.clipboard = Some(if let Some(text) = text.strip_suffix('\n') {
+      RegisterContent::Linewise(text.split('\n').map(String::from).collect())
+    } else {
+      RegisterContent::Charwise(text)
+    })
+  }
+
+  fn set_named(&mut self, name: char, content: RegisterContent) {
+    let key = name.to_ascii_lowercase();
+    if name.is_ascii_uppercase() {
+      if let Some(existing) = self.named.get_mut(&key) {
+        existing.append(content);
+        return
+      }
+    }
+    self.named.insert(key, content);
+  }
+}
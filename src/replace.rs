@@ -0,0 +1,99 @@
+use crate::quickfix::QuickfixEntry;
+use crate::substitute::Substitute;
+
+// one line replaced by the lines it became, with 0-based row numbers in the
+// file before and after the change
+#[derive(Debug)]
+pub struct Hunk {
+  pub old_row: usize,
+  pub new_row: usize,
+  pub old: String,
+  pub new: Vec<String>
+}
+
+// what a substitution across files would do to one of them
+#[derive(Debug)]
+pub struct FileChange {
+  pub file: String,
+  pub before: Vec<String>,
+  pub after: Vec<String>,
+  pub hunks: Vec<Hunk>,
+  pub replaced: usize
+}
+
+// the files of the quickfix list in order, each with the rows its entries are
+// on, or None for every row when 'whole_files' is set (:cfdo rather than :cdo)
+fn targets(entries: &[QuickfixEntry], whole_files: bool) -> Vec<(String, Option<Vec<usize>>)> {
+  let mut targets: Vec<(String, Option<Vec<usize>>)> = Vec::new();
+  for entry in entries {
+    match targets.iter_mut().find(|(file, _)| *file == entry.file) {
+      Some((_, Some(rows))) => {
+        if !rows.contains(&entry.row) {
+          rows.push(entry.row)
+        }
+      },
+      Some((_, None)) => (),
+      None => targets.push((entry.file.clone(), if whole_files { None } else { Some(vec![entry.row]) }))
+    }
+  }
+  targets
+}
+
+// works out the substitution for every file in the list without touching
+// any, with 'read' giving the lines of a file as they are being edited
+pub fn plan<F: Fn(&str) -> Result<Vec<String>, String>>(
+  substitute: &Substitute,
+  entries: &[QuickfixEntry],
+  whole_files: bool,
+  read: F
+) -> Result<Vec<FileChange>, String> {
+  let mut changes = Vec::new();
+  for (file, rows) in targets(entries, whole_files) {
+    let before = read(&file)?;
+    let mut after = Vec::new();
+    let mut hunks = Vec::new();
+    let mut replaced = 0;
+    for (row, line) in before.iter().enumerate() {
+      let selected = rows.as_ref().is_none_or(|rows| rows.contains(&row));
+      match substitute.replace_line(line).filter(|_| selected) {
+        Some((new_line, count)) => {
+          let new: Vec<String> = new_line.split('\n').map(String::from).collect();
+          hunks.push(Hunk {
+            old_row: row,
+            new_row: after.len(),
+            old: line.clone(),
+            new: new.clone()
+          });
+          after.extend(new);
+          replaced += count
+        },
+        None => after.push(line.clone())
+      }
+    }
+    if replaced > 0 {
+      changes.push(FileChange {
+        file,
+        before,
+        after,
+        hunks,
+        replaced
+      })
+    }
+  }
+  Ok(changes)
+}
+
+// a diff of the planned changes, one hunk per replaced line
+pub fn preview(changes: &[FileChange]) -> Vec<String> {
+  let mut lines = Vec::new();
+  for change in changes {
+    lines.push(format!("--- {}", change.file));
+    lines.push(format!("+++ {}", change.file));
+    for hunk in &change.hunks {
+      lines.push(format!("@@ -{},1 +{},{} @@", hunk.old_row + 1, hunk.new_row + 1, hunk.new.len()));
+      lines.push(format!("-{}", hunk.old));
+      lines.extend(hunk.new.iter().map(|line| format!("+{}", line)))
+    }
+  }
+  lines
+}
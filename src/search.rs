@@ -0,0 +1,114 @@
+use regex::{Regex, RegexBuilder};
+use crate::substitute::parse_part;
+
+// set with :set [no]ignorecase, [no]smartcase and [no]literal
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SearchOptions {
+  pub ignore_case: bool,
+  // with ignore_case, a pattern containing uppercase still matches case
+  pub smart_case: bool,
+  // patterns are plain text rather than regular expressions
+  pub literal: bool
+}
+
+// where the cursor goes relative to a match, as in /pattern/e+1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchOffset {
+  // lines below (or above) the match, in the first column
+  Line(i64),
+  // columns from the match's first character
+  Start(i64),
+  // columns from the match's last character
+  End(i64)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchPattern {
+  pub pattern: String,
+  pub backward: bool,
+  pub offset: Option<SearchOffset>
+}
+
+fn parse_amount(text: &str) -> Option<i64> {
+  match text {
+    "" => Some(0),
+    "+" => Some(1),
+    "-" => Some(-1),
+    text => text.strip_prefix('+').unwrap_or(text).parse().ok()
+  }
+}
+
+fn parse_offset(text: &str) -> Option<SearchOffset> {
+  let mut chars = text.chars();
+  match chars.next()? {
+    'e' => Some(SearchOffset::End(parse_amount(chars.as_str())?)),
+    's' | 'b' => Some(SearchOffset::Start(parse_amount(chars.as_str())?)),
+    _ => Some(SearchOffset::Line(parse_amount(text)?))
+  }
+}
+
+// parses a search prompt, /pattern[/offset] or ?pattern[?offset]; an empty
+// pattern stands for the previous one
+pub fn parse(prompt: &str) -> Option<SearchPattern> {
+  let chars: Vec<char> = prompt.chars().collect();
+  let delimiter = match chars.first() {
+    Some(ch @ ('/' | '?')) => *ch,
+    _ => return None
+  };
+  let mut idx = 1;
+  let pattern = parse_part(&chars, &mut idx, delimiter);
+  let offset = parse_offset(&chars[idx.min(chars.len())..].iter().collect::<String>());
+  Some(SearchPattern {
+    pattern,
+    backward: delimiter == '?',
+    offset
+  })
+}
+
+// whether the pattern has an uppercase letter that is not part of an escape like \W
+fn has_uppercase(pattern: &str) -> bool {
+  let mut escaped = false;
+  pattern.chars().any(|ch| {
+    let upper = !escaped && ch.is_uppercase();
+    escaped = !escaped && ch == '\\';
+    upper
+  })
+}
+
+// builds the regex for a pattern: \c or \C anywhere in it ignores or matches
+// case, a leading \V makes it literal and a leading \m a regex whatever the options
+pub fn compile(pattern: &str, options: &SearchOptions) -> Result<Regex, String> {
+  let mut case = None;
+  let mut text = String::new();
+  let mut chars = pattern.chars();
+  while let Some(ch) = chars.next() {
+    match (ch, chars.clone().next()) {
+      ('\\', Some(flag @ ('c' | 'C'))) => {
+        case = Some(flag == 'c');
+        chars.next();
+      },
+      ('\\', Some(next)) => {
+        text.push(ch);
+        text.push(next);
+        chars.next();
+      },
+      _ => text.push(ch)
+    }
+  }
+  let (literal, text) = if let Some(rest) = text.strip_prefix("\\V") {
+    (true, rest)
+  } else if let Some(rest) = text.strip_prefix("\\m") {
+    (false, rest)
+  } else {
+    (options.literal, text.as_str())
+  };
+  let ignore_case = case.unwrap_or(options.ignore_case && !(options.smart_case && has_uppercase(text)));
+  let source = if literal { regex::escape(text) } else { text.to_string() };
+  RegexBuilder::new(&source)
+    .case_insensitive(ignore_case)
+    .build()
+    .map_err(|why| match why {
+      regex::Error::Syntax(message) => message.lines().last().unwrap_or("invalid pattern").trim().to_string(),
+      why => why.to_string()
+    })
+}
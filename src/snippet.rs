@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use dirs::home_dir;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::highlighting::Language;
+use crate::motion::Position;
+
+// one place a tab stop appears: the first is where it is typed, the rest
+// mirror it; columns and lengths are in graphemes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+  pub number: usize,
+  pub row: usize,
+  pub column: usize,
+  pub len: usize
+}
+
+// a snippet body ready to be inserted, its fields placed relative to where
+// the insertion starts: rows counted from it, columns from it on its first row
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+  pub text: String,
+  pub fields: Vec<Field>
+}
+
+// the fields of an expanded snippet while Tab moves through them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+  pub fields: Vec<Field>,
+  // the number of the field being typed in
+  pub current: usize,
+  // whether the next key typed replaces the field's placeholder
+  pub fresh: bool
+}
+
+// where a stop comes when Tab moves through them: by number, 0 last
+fn order(number: usize) -> usize {
+  if number == 0 { usize::MAX } else { number }
+}
+
+enum Piece {
+  Text(String),
+  Field(usize, Option<String>)
+}
+
+// the snippets for 'language' from ~/.editrc/snippets/<language>.json, each
+// name mapping to its body as a string or a list of lines
+pub fn load(language: Language) -> HashMap<String, String> {
+  let name = match language {
+    Language::Rust => "rust",
+    Language::Python => "python",
+    Language::C => "c"
+  };
+  let config = home_dir()
+    .and_then(|home| read_to_string(home.join(".editrc/snippets").join(format!("{}.json", name))).ok())
+    .and_then(|content| json::parse(&content).ok());
+  let mut snippets = HashMap::new();
+  if let Some(config) = config {
+    for (name, body) in config.entries() {
+      let body = if body.is_array() {
+        body.members().filter_map(|line| line.as_str()).collect::<Vec<&str>>().join("\n")
+      } else {
+        match body.as_str() {
+          Some(body) => body.to_string(),
+          None => continue
+        }
+      };
+      snippets.insert(name.to_string(), body);
+    }
+  }
+  snippets
+}
+
+// splits a body into text and $1, ${1} or ${1:placeholder} fields; a
+// backslash keeps the next character as it is
+fn pieces(body: &str) -> Vec<Piece> {
+  let chars: Vec<char> = body.chars().collect();
+  let mut pieces = Vec::new();
+  let mut text = String::new();
+  let mut idx = 0;
+  while idx < chars.len() {
+    match chars[idx] {
+      '\\' if idx + 1 < chars.len() => {
+        text.push(chars[idx + 1]);
+        idx += 2;
+        continue
+      },
+      '$' => {
+        let braced = chars.get(idx + 1) == Some(&'{');
+        let digits_start = if braced { idx + 2 } else { idx + 1 };
+        let digits: String = chars[digits_start.min(chars.len())..].iter().take_while(|ch| ch.is_ascii_digit()).collect();
+        if let Ok(number) = digits.parse::<usize>() {
+          let mut end = digits_start + digits.len();
+          let mut placeholder = None;
+          if braced {
+            if chars.get(end) == Some(&':') {
+              let mut inner = String::new();
+              end += 1;
+              while end < chars.len() && chars[end] != '}' {
+                if chars[end] == '\\' && end + 1 < chars.len() {
+                  end += 1
+                }
+                inner.push(chars[end]);
+                end += 1
+              }
+              placeholder = Some(inner)
+            }
+            if chars.get(end) != Some(&'}') {
+              text.push('$');
+              idx += 1;
+              continue
+            }
+            end += 1
+          }
+          pieces.push(Piece::Text(std::mem::take(&mut text)));
+          pieces.push(Piece::Field(number, placeholder));
+          idx = end;
+          continue
+        }
+      },
+      _ => ()
+    }
+    text.push(chars[idx]);
+    idx += 1
+  }
+  pieces.push(Piece::Text(text));
+  pieces
+}
+
+// the body as it is inserted on a row indented by 'indent': later lines get
+// the same indentation, tabs become two spaces and every appearance of a
+// field shows the placeholder given to it anywhere
+pub fn expand(body: &str, indent: &str) -> Expansion {
+  let pieces = pieces(&body.replace('\t', "  "));
+  let mut placeholders: HashMap<usize, String> = HashMap::new();
+  for piece in &pieces {
+    if let Piece::Field(number, Some(placeholder)) = piece {
+      placeholders.entry(*number).or_insert_with(|| placeholder.clone());
+    }
+  }
+  let mut text = String::new();
+  let mut fields = Vec::new();
+  let (mut row, mut column) = (0, 0);
+  for piece in pieces {
+    let piece_text = match piece {
+      Piece::Text(piece_text) => piece_text,
+      Piece::Field(number, _) => {
+        let placeholder = placeholders.get(&number).cloned().unwrap_or_default();
+        let len = placeholder.graphemes(true).count();
+        fields.push(Field { number, row, column, len });
+        placeholder
+      }
+    };
+    for (idx, line) in piece_text.split('\n').enumerate() {
+      if idx > 0 {
+        text.push('\n');
+        text.push_str(indent);
+        row += 1;
+        column = indent.graphemes(true).count()
+      }
+      text.push_str(line);
+      column += line.graphemes(true).count()
+    }
+  }
+  Expansion { text, fields }
+}
+
+impl Session {
+  // the fields of 'expansion' once inserted at 'at', None if it has none
+  pub fn start(expansion: &Expansion, at: Position) -> Option<Self> {
+    let fields: Vec<Field> = expansion.fields
+      .iter()
+      .map(|field| Field {
+        row: at.0 + field.row,
+        column: if field.row == 0 { at.1 + field.column } else { field.column },
+        ..field.clone()
+      })
+      .collect();
+    let first = fields.iter().map(|field| field.number).min_by_key(|number| order(*number))?;
+    Some(Self {
+      fields,
+      current: first,
+      fresh: true
+    })
+  }
+
+  // where the field being typed in is typed, the first appearance of its number
+  pub fn field(&self) -> Option<&Field> {
+    self.fields.iter().find(|field| field.number == self.current)
+  }
+
+  // the number of the stop after (or before) the current one, 0 coming last
+  pub fn next(&self, backward: bool) -> Option<usize> {
+    let numbers = self.fields.iter().map(|field| field.number);
+    if backward {
+      numbers.filter(|number| order(*number) < order(self.current)).max_by_key(|number| order(*number))
+    } else {
+      numbers.filter(|number| order(*number) > order(self.current)).min_by_key(|number| order(*number))
+    }
+  }
+
+  // keeps the fields in place once the text from 'column' on 'row' has
+  // grown (or shrunk) by 'delta' graphemes
+  pub fn shift(&mut self, row: usize, column: usize, delta: isize) {
+    for field in self.fields.iter_mut().filter(|field| field.row == row && field.column > column) {
+      field.column = (field.column as isize + delta) as usize
+    }
+  }
+}
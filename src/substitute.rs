@@ -0,0 +1,244 @@
+use regex::{Captures, Regex};
+use crate::search::{self, SearchOptions};
+
+// a line in a command range, before the offset is applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Address {
+  // 1-based line number
+  Line(usize),
+  Current,
+  Last,
+  Mark(char)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineRange {
+  Whole,
+  Lines((Address, i64), (Address, i64))
+}
+
+#[derive(Debug)]
+pub struct Substitute {
+  pub range: LineRange,
+  pub regex: Regex,
+  replacement: String,
+  pub global: bool,
+  pub confirm: bool,
+  // report the number of matches without replacing
+  pub count_only: bool,
+  // the range is this many lines starting at its last line
+  pub count: Option<usize>
+}
+
+fn parse_number(chars: &[char], idx: &mut usize) -> Option<usize> {
+  let digits = chars[*idx..].iter().take_while(|ch| ch.is_ascii_digit()).count();
+  if digits == 0 {
+    return None
+  }
+  let value = chars[*idx..(*idx + digits)].iter().collect::<String>().parse().ok();
+  *idx += digits;
+  value
+}
+
+// [number | . | $ | 'x] followed by any number of +N / -N offsets
+fn parse_address(chars: &[char], idx: &mut usize) -> Option<(Address, i64)> {
+  let start = *idx;
+  let address = match chars.get(*idx) {
+    Some('.') => {
+      *idx += 1;
+      Address::Current
+    },
+    Some('$') => {
+      *idx += 1;
+      Address::Last
+    },
+    Some('\'') => {
+      let name = *chars.get(*idx + 1)?;
+      *idx += 2;
+      Address::Mark(name)
+    },
+    Some(ch) if ch.is_ascii_digit() => Address::Line(parse_number(chars, idx)?),
+    _ => Address::Current
+  };
+  let mut offset = 0;
+  while let Some(sign @ ('+' | '-')) = chars.get(*idx) {
+    *idx += 1;
+    let amount = parse_number(chars, idx).unwrap_or(1) as i64;
+    offset += if *sign == '+' { amount } else { -amount }
+  }
+  if *idx == start {
+    None
+  } else {
+    Some((address, offset))
+  }
+}
+
+fn parse_range(chars: &[char], idx: &mut usize) -> Option<LineRange> {
+  if chars.get(*idx) == Some(&'%') {
+    *idx += 1;
+    return Some(LineRange::Whole)
+  }
+  let first = parse_address(chars, idx)?;
+  if chars.get(*idx) == Some(&',') {
+    *idx += 1;
+    let second = parse_address(chars, idx).unwrap_or((Address::Current, 0));
+    Some(LineRange::Lines(first, second))
+  } else {
+    Some(LineRange::Lines(first, first))
+  }
+}
+
+// reads up to the next unescaped 'delimiter', an escaped delimiter stands for
+// itself (keeping its backslash where the regex needs it, as with \?)
+pub fn parse_part(chars: &[char], idx: &mut usize, delimiter: char) -> String {
+  let mut part = String::new();
+  while let Some(ch) = chars.get(*idx) {
+    *idx += 1;
+    match ch {
+      '\\' if chars.get(*idx) == Some(&delimiter) => {
+        if regex::escape(&delimiter.to_string()).len() > 1 {
+          part.push('\\')
+        }
+        part.push(delimiter);
+        *idx += 1
+      },
+      '\\' => {
+        part.push('\\');
+        if let Some(escaped) = chars.get(*idx) {
+          part.push(*escaped);
+          *idx += 1
+        }
+      },
+      ch if *ch == delimiter => break,
+      ch => part.push(*ch)
+    }
+  }
+  part
+}
+
+// parses [range]s/pattern/replacement/[flags] [count], None if the command
+// is not a substitution at all; the i and I flags override the case options
+pub fn parse(command: &str, options: &SearchOptions) -> Option<Result<Substitute, String>> {
+  let chars: Vec<char> = command.trim().chars().collect();
+  let mut idx = 0;
+  let range = parse_range(&chars, &mut idx).unwrap_or(LineRange::Lines((Address::Current, 0), (Address::Current, 0)));
+  if chars.get(idx) != Some(&'s') {
+    return None
+  }
+  idx += 1;
+  let delimiter = match chars.get(idx) {
+    Some(ch) if !ch.is_alphanumeric() && !ch.is_whitespace() && *ch != '\\' && *ch != '"' => *ch,
+    _ => return None
+  };
+  idx += 1;
+  let pattern = parse_part(&chars, &mut idx, delimiter);
+  let replacement = parse_part(&chars, &mut idx, delimiter);
+  let mut substitute = Substitute {
+    range,
+    regex: Regex::new("").unwrap(),
+    replacement,
+    global: false,
+    confirm: false,
+    count_only: false,
+    count: None
+  };
+  let mut options = *options;
+  while let Some(flag) = chars.get(idx) {
+    match flag {
+      'g' => substitute.global = true,
+      'c' => substitute.confirm = true,
+      'n' => substitute.count_only = true,
+      'i' => {
+        options.ignore_case = true;
+        options.smart_case = false
+      },
+      'I' => options.ignore_case = false,
+      ' ' => (),
+      ch if ch.is_ascii_digit() => {
+        substitute.count = parse_number(&chars, &mut idx).filter(|count| *count > 0);
+        continue
+      },
+      flag => return Some(Err(format!("unknown flag: {}", flag)))
+    }
+    idx += 1
+  }
+  if pattern.is_empty() {
+    return Some(Err(String::from("empty pattern")))
+  }
+  substitute.regex = match search::compile(&pattern, &options) {
+    Ok(regex) => regex,
+    Err(why) => return Some(Err(why))
+  };
+  Some(Ok(substitute))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Case {
+  Keep,
+  Upper,
+  Lower
+}
+
+fn push_cased(text: &mut String, part: &str, one_shot: &mut Option<Case>, case: Case) {
+  for ch in part.chars() {
+    let applied = one_shot.take().unwrap_or(case);
+    match applied {
+      Case::Upper => text.extend(ch.to_uppercase()),
+      Case::Lower => text.extend(ch.to_lowercase()),
+      Case::Keep => text.push(ch)
+    }
+  }
+}
+
+impl Substitute {
+  // the line with its matches replaced (only the first without the g flag) and
+  // how many there were, None if nothing matched
+  pub fn replace_line(&self, line: &str) -> Option<(String, usize)> {
+    let mut replaced = String::new();
+    let mut consumed = 0;
+    let mut count = 0;
+    for captures in self.regex.captures_iter(line).take(if self.global { usize::MAX } else { 1 }) {
+      let found = captures.get(0).unwrap();
+      replaced.push_str(&line[consumed..found.start()]);
+      replaced.push_str(&self.expand(&captures));
+      consumed = found.end();
+      count += 1
+    }
+    if count == 0 {
+      return None
+    }
+    replaced.push_str(&line[consumed..]);
+    Some((replaced, count))
+  }
+
+  // the replacement text for one match: & and \0 insert the whole match,
+  // \1-\9 a capture group, \n or \r a line break, \u \l \U \L \E change case
+  pub fn expand(&self, captures: &Captures) -> String {
+    let mut text = String::new();
+    let mut case = Case::Keep;
+    let mut one_shot = None;
+    let mut chars = self.replacement.chars();
+    while let Some(ch) = chars.next() {
+      match ch {
+        '&' => push_cased(&mut text, &captures[0], &mut one_shot, case),
+        '\\' => match chars.next() {
+          Some(digit @ '0'..='9') => {
+            let group = captures.get(digit.to_digit(10).unwrap() as usize).map(|group| group.as_str()).unwrap_or("");
+            push_cased(&mut text, group, &mut one_shot, case)
+          },
+          Some('n') | Some('r') => text.push('\n'),
+          Some('t') => text.push('\t'),
+          Some('u') => one_shot = Some(Case::Upper),
+          Some('l') => one_shot = Some(Case::Lower),
+          Some('U') => case = Case::Upper,
+          Some('L') => case = Case::Lower,
+          Some('E') | Some('e') => case = Case::Keep,
+          Some(escaped) => push_cased(&mut text, &escaped.to_string(), &mut one_shot, case),
+          None => text.push('\\')
+        },
+        ch => push_cased(&mut text, &ch.to_string(), &mut one_shot, case)
+      }
+    }
+    text
+  }
+}
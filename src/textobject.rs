@@ -0,0 +1,435 @@
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::file::Document;
+use crate::highlighting::{in_literal, Language, Token, TokenKind};
+use crate::motion::{Position, Span, CharClass, class_of, code_graphemes, graphemes, first_non_blank};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextObject {
+  Word,
+  Pair(char, char),
+  Quote(char),
+  Paragraph,
+  Function,
+  Comment
+}
+
+impl TextObject {
+  // the object named by the key following 'i' or 'a'
+  pub fn from_key(key: char) -> Option<Self> {
+    match key {
+      'w' => Some(TextObject::Word),
+      '(' | ')' | 'b' => Some(TextObject::Pair('(', ')')),
+      '{' | '}' | 'B' => Some(TextObject::Pair('{', '}')),
+      '[' | ']' => Some(TextObject::Pair('[', ']')),
+      '<' | '>' => Some(TextObject::Pair('<', '>')),
+      '"' | '\'' | '`' => Some(TextObject::Quote(key)),
+      'p' => Some(TextObject::Paragraph),
+      'f' => Some(TextObject::Function),
+      'c' => Some(TextObject::Comment),
+      _ => None
+    }
+  }
+}
+
+// the span an object covers around the cursor; 'inner' selects i over a
+pub fn select(file: &Document, pos: Position, object: TextObject, inner: bool, count: usize) -> Option<Span> {
+  match object {
+    TextObject::Word => select_word(file, pos, inner, count),
+    TextObject::Pair(open, close) => select_pair(file, pos, open, close, inner, count),
+    TextObject::Quote(quote) => select_quote(file, pos, quote, inner),
+    TextObject::Paragraph => select_paragraph(file, pos, inner, count),
+    TextObject::Function => select_function(file, pos, inner, count),
+    TextObject::Comment => select_comment(file, pos, inner)
+  }
+}
+
+fn select_word(file: &Document, pos: Position, inner: bool, count: usize) -> Option<Span> {
+  let row = graphemes(file, pos.0);
+  if row.is_empty() {
+    return None
+  }
+  let column = pos.1.min(row.len() - 1);
+  let class = class_of(row[column]);
+  let run_start = |from: usize| {
+    let class = class_of(row[from]);
+    (0..from).rev().take_while(|idx| class_of(row[*idx]) == class).last().unwrap_or(from)
+  };
+  let run_end = |from: usize| {
+    let class = class_of(row[from]);
+    ((from + 1)..row.len()).take_while(|idx| class_of(row[*idx]) == class).last().map(|idx| idx + 1).unwrap_or(from + 1)
+  };
+  let mut start = run_start(column);
+  let mut end = run_end(column);
+  // every further count takes in the next run ('iw') or the next word with its blanks ('aw')
+  for _ in 1..count {
+    if end >= row.len() {
+      break
+    }
+    end = run_end(end);
+    if !inner && class_of(row[end - 1]) == CharClass::Blank && end < row.len() {
+      end = run_end(end)
+    }
+  }
+  if !inner {
+    if class == CharClass::Blank {
+      if end < row.len() {
+        end = run_end(end)
+      }
+    } else if end < row.len() && class_of(row[end]) == CharClass::Blank {
+      end = run_end(end)
+    } else if start > 0 && class_of(row[start - 1]) == CharClass::Blank {
+      start = run_start(start - 1)
+    }
+  }
+  Some(Span::Chars((pos.0, start), (pos.0, end)))
+}
+
+// the nearest unmatched 'open' at or before the cursor; a 'close' under
+// the cursor itself is not counted so it selects the pair it closes, and
+// brackets in strings, chars and comments don't count
+fn find_open(file: &Document, tokens: &Option<Vec<Vec<Token>>>, pos: Position, open: &str, close: &str) -> Option<Position> {
+  let mut depth = 0;
+  for row_no in (0..=pos.0).rev() {
+    let row = code_graphemes(file, tokens, row_no);
+    let last = if row_no == pos.0 { pos.1.min(row.len().saturating_sub(1)) } else { row.len().saturating_sub(1) };
+    if row.is_empty() {
+      continue
+    }
+    for column in (0..=last).rev() {
+      if row[column] == close && (row_no, column) != pos {
+        depth += 1
+      } else if row[column] == open {
+        if depth == 0 {
+          return Some((row_no, column))
+        }
+        depth -= 1
+      }
+    }
+  }
+  None
+}
+
+fn find_close(file: &Document, tokens: &Option<Vec<Vec<Token>>>, open_pos: Position, open: &str, close: &str) -> Option<Position> {
+  let mut depth = 0;
+  for row_no in open_pos.0..file.len() {
+    let row = code_graphemes(file, tokens, row_no);
+    let first = if row_no == open_pos.0 { open_pos.1 + 1 } else { 0 };
+    for (column, grapheme) in row.iter().enumerate().skip(first) {
+      if *grapheme == open {
+        depth += 1
+      } else if *grapheme == close {
+        if depth == 0 {
+          return Some((row_no, column))
+        }
+        depth -= 1
+      }
+    }
+  }
+  None
+}
+
+fn select_pair(file: &Document, pos: Position, open: char, close: char, inner: bool, count: usize) -> Option<Span> {
+  let (open, close) = (open.to_string(), close.to_string());
+  let tokens = file.tokens();
+  let mut open_pos = find_open(file, &tokens, pos, &open, &close)?;
+  for _ in 1..count {
+    if open_pos.1 == 0 && open_pos.0 == 0 {
+      return None
+    }
+    let before = if open_pos.1 > 0 {
+      (open_pos.0, open_pos.1 - 1)
+    } else {
+      (open_pos.0 - 1, graphemes(file, open_pos.0 - 1).len())
+    };
+    open_pos = find_open(file, &tokens, before, &open, &close)?
+  }
+  let close_pos = find_close(file, &tokens, open_pos, &open, &close)?;
+  if !inner {
+    return Some(Span::Chars(open_pos, (close_pos.0, close_pos.1 + 1)))
+  }
+  let open_row_len = graphemes(file, open_pos.0).len();
+  // a block whose brackets sit on their own rows selects the rows in between
+  if close_pos.0 > open_pos.0 + 1
+    && open_pos.1 + 1 == open_row_len
+    && first_non_blank(file, close_pos.0) == close_pos.1 {
+    return Some(Span::Lines((open_pos.0 + 1)..close_pos.0))
+  }
+  Some(Span::Chars((open_pos.0, open_pos.1 + 1), close_pos))
+}
+
+// the byte ranges of the string or char token under or after the cursor that
+// 'quote' delimits: the whole of it from the opening quote, and its inside
+fn quoted_token(file: &Document, pos: Position, tokens: &[Token], quote: char) -> Option<(Range<usize>, Range<usize>)> {
+  let cursor_idx = file.rows[pos.0].byte_index(pos.1);
+  tokens
+    .iter()
+    .filter(|token| matches!(token.kind, TokenKind::String | TokenKind::Char) && token.range.end > cursor_idx)
+    .find_map(|token| {
+      let text = token_text(file, pos.0, token).trim_end();
+      let start = token.range.start + text.find(quote)?;
+      let end = token.range.start + text.len();
+      if end - start < 2 * quote.len_utf8() || !text.ends_with(quote) {
+        return None
+      }
+      Some((start..end, (start + quote.len_utf8())..(end - quote.len_utf8())))
+    })
+}
+
+// the same for a row without a lexer, pairing quotes by position; a quote
+// after an odd number of backslashes is escaped
+fn quoted_by_position(file: &Document, pos: Position, quote: char) -> Option<(Range<usize>, Range<usize>)> {
+  let content = file.rows[pos.0].content();
+  let cursor_idx = file.rows[pos.0].byte_index(pos.1);
+  let quotes: Vec<usize> = content
+    .match_indices(quote)
+    .map(|(idx, _)| idx)
+    .filter(|idx| content[..*idx].chars().rev().take_while(|ch| *ch == '\\').count() % 2 == 0)
+    .collect();
+  let pairs = || quotes.chunks(2).filter(|pair| pair.len() == 2);
+  let pair = pairs()
+    .find(|pair| pair[0] <= cursor_idx && cursor_idx <= pair[1])
+    .or_else(|| pairs().find(|pair| pair[0] > cursor_idx))?;
+  let (open, close) = (pair[0], pair[1] + quote.len_utf8());
+  Some((open..close, (open + quote.len_utf8())..pair[1]))
+}
+
+fn select_quote(file: &Document, pos: Position, quote: char, inner: bool) -> Option<Span> {
+  let (outer, inside) = match file.row_tokens(pos.0) {
+    Some(tokens) => quoted_token(file, pos, &tokens, quote)?,
+    None => quoted_by_position(file, pos, quote)?
+  };
+  let content = file.rows[pos.0].content();
+  let column = |idx: usize| content[..idx].graphemes(true).count();
+  if inner {
+    return Some(Span::Chars((pos.0, column(inside.start)), (pos.0, column(inside.end))))
+  }
+  let row = graphemes(file, pos.0);
+  let mut start = column(outer.start);
+  let mut end = column(outer.end);
+  // 'a' takes trailing blanks, or leading ones when there are none
+  let trailing = (end..row.len()).take_while(|idx| class_of(row[*idx]) == CharClass::Blank).count();
+  if trailing > 0 {
+    end += trailing
+  } else {
+    start -= (0..start).rev().take_while(|idx| class_of(row[*idx]) == CharClass::Blank).count()
+  }
+  Some(Span::Chars((pos.0, start), (pos.0, end)))
+}
+
+fn is_blank_row(file: &Document, row: usize) -> bool {
+  file.rows[row].content().trim().is_empty()
+}
+
+// first row past 'row' that differs from it in being blank
+fn paragraph_end(file: &Document, row: usize) -> usize {
+  let blank = is_blank_row(file, row);
+  (row..file.len()).find(|idx| is_blank_row(file, *idx) != blank).unwrap_or_else(|| file.len())
+}
+
+fn select_paragraph(file: &Document, pos: Position, inner: bool, count: usize) -> Option<Span> {
+  let blank = is_blank_row(file, pos.0);
+  let mut start = pos.0;
+  while start > 0 && is_blank_row(file, start - 1) == blank {
+    start -= 1
+  }
+  let mut end = paragraph_end(file, pos.0);
+  for _ in 1..count {
+    if end < file.len() {
+      end = paragraph_end(file, end)
+    }
+    if !inner && end < file.len() && is_blank_row(file, end) != blank {
+      end = paragraph_end(file, end)
+    }
+  }
+  if !inner {
+    // 'ap' takes the blank rows after the paragraph, or those before it at the end of the file
+    if end < file.len() {
+      end = paragraph_end(file, end)
+    } else if !blank {
+      while start > 0 && is_blank_row(file, start - 1) {
+        start -= 1
+      }
+    }
+  }
+  Some(Span::Lines(start..end))
+}
+
+fn token_text<'a>(file: &'a Document, row: usize, token: &Token) -> &'a str {
+  &file.rows[row].content()[token.range.clone()]
+}
+
+fn indentation(file: &Document, row: usize) -> usize {
+  file.rows[row].content().chars().take_while(|ch| ch.is_whitespace()).count()
+}
+
+fn is_definition(file: &Document, language: Language, row: usize, tokens: &[Token]) -> bool {
+  let keyword = |word: &str| tokens
+    .iter()
+    .any(|token| token.kind == TokenKind::Keyword && token_text(file, row, token).trim() == word);
+  match language {
+    Language::Rust => keyword("fn"),
+    Language::Python => keyword("def"),
+    Language::C => {
+      let content = file.rows[row].content();
+      // a function name at the top level of the file that is not a prototype
+      indentation(file, row) == 0
+        && !content.starts_with('#')
+        && !content.trim_end().ends_with(';')
+        && tokens.iter().any(|token| token.kind == TokenKind::Function)
+        && tokens
+          .iter()
+          .find(|token| !token_text(file, row, token).trim().is_empty())
+          .is_some_and(|token| token.kind != TokenKind::Keyword)
+    }
+  }
+}
+
+// positions of the body braces of a brace delimited function defined on 'row'
+fn brace_body(file: &Document, tokens: &[Vec<Token>], row: usize) -> Option<(Position, Position)> {
+  let mut open = None;
+  let mut depth = 0;
+  for (row_no, row_tokens) in tokens.iter().enumerate().skip(row) {
+    let content = file.rows[row_no].content();
+    for (column, (idx, grapheme)) in content.grapheme_indices(true).enumerate() {
+      if in_literal(row_tokens, idx) {
+        continue
+      }
+      match grapheme {
+        ";" if open.is_none() && depth == 0 => return None,
+        "(" | "[" if open.is_none() => depth += 1,
+        ")" | "]" if open.is_none() => depth -= 1,
+        "{" => {
+          if open.is_none() {
+            open = Some((row_no, column))
+          }
+          depth += 1
+        },
+        "}" if open.is_some() => {
+          depth -= 1;
+          if depth == 0 {
+            return Some((open.unwrap(), (row_no, column)))
+          }
+        },
+        _ => ()
+      }
+    }
+  }
+  None
+}
+
+fn select_function(file: &Document, pos: Position, inner: bool, count: usize) -> Option<Span> {
+  let language = file.language()?;
+  let tokens = file.tokens()?;
+  let mut found = 0;
+  for row in (0..=pos.0).rev() {
+    if !is_definition(file, language, row, &tokens[row]) {
+      continue
+    }
+    let span = match language {
+      Language::Rust | Language::C => {
+        let (open, close) = match brace_body(file, &tokens, row) {
+          Some(body) => body,
+          None => continue
+        };
+        if close.0 < pos.0 {
+          continue
+        }
+        if !inner {
+          Span::Lines(row..(close.0 + 1))
+        } else if close.0 > open.0 + 1
+          && open.1 + 1 == graphemes(file, open.0).len()
+          && first_non_blank(file, close.0) == close.1 {
+          Span::Lines((open.0 + 1)..close.0)
+        } else {
+          Span::Chars((open.0, open.1 + 1), close)
+        }
+      },
+      Language::Python => {
+        let indent = indentation(file, row);
+        let last = ((row + 1)..file.len())
+          .take_while(|idx| is_blank_row(file, *idx) || indentation(file, *idx) > indent)
+          .filter(|idx| !is_blank_row(file, *idx))
+          .last();
+        let last = match last {
+          Some(last) if last >= pos.0 => last,
+          _ => continue
+        };
+        Span::Lines(if inner { row + 1 } else { row }..(last + 1))
+      }
+    };
+    found += 1;
+    if found == count {
+      return Some(span)
+    }
+  }
+  None
+}
+
+// the functions defined in 'file', as where their name starts and the name
+pub fn functions(file: &Document) -> Vec<(Position, String)> {
+  let (language, tokens) = match (file.language(), file.tokens()) {
+    (Some(language), Some(tokens)) => (language, tokens),
+    _ => return Vec::new()
+  };
+  tokens
+    .iter()
+    .enumerate()
+    .filter(|(row, row_tokens)| is_definition(file, language, *row, row_tokens))
+    .filter_map(|(row, row_tokens)| {
+      let token = row_tokens.iter().find(|token| token.kind == TokenKind::Function)?;
+      let column = file.rows[row].content()[..token.range.start].graphemes(true).count();
+      Some(((row, column), token_text(file, row, token).trim().to_string()))
+    })
+    .collect()
+}
+
+fn is_comment_row(file: &Document, row: usize, tokens: &[Token]) -> bool {
+  tokens.iter().any(|token| token.kind == TokenKind::Comment)
+    && tokens
+      .iter()
+      .filter(|token| token.kind != TokenKind::Comment)
+      .all(|token| token_text(file, row, token).trim().is_empty())
+}
+
+fn select_comment(file: &Document, pos: Position, inner: bool) -> Option<Span> {
+  let tokens = file.tokens()?;
+  if is_comment_row(file, pos.0, &tokens[pos.0]) {
+    let mut start = pos.0;
+    while start > 0 && is_comment_row(file, start - 1, &tokens[start - 1]) {
+      start -= 1
+    }
+    let mut end = pos.0 + 1;
+    while end < file.len() && is_comment_row(file, end, &tokens[end]) {
+      end += 1
+    }
+    if !inner {
+      while end < file.len() && is_blank_row(file, end) {
+        end += 1
+      }
+    }
+    return Some(Span::Lines(start..end))
+  }
+  // a trailing comment on a row of code
+  let content = file.rows[pos.0].content();
+  let cursor_idx = file.rows[pos.0].byte_index(pos.1);
+  let comments: Vec<&Token> = tokens[pos.0].iter().filter(|token| token.kind == TokenKind::Comment).collect();
+  let first = comments.iter().find(|token| token.range.end > cursor_idx)?;
+  let last = comments.iter().rev().find(|token| token.range.start >= first.range.start)?;
+  let mut range = first.range.start..last.range.end;
+  if inner {
+    let text = &content[range.clone()];
+    let leader = ["//", "/*", "#"].iter().find(|leader| text.starts_with(**leader)).map_or(0, |leader| leader.len());
+    let trailer = if text.ends_with("*/") && text.len() >= leader + 2 { 2 } else { 0 };
+    let body = &text[leader..(text.len() - trailer)];
+    // nothing but the comment markers has no inside to select
+    if body.trim().is_empty() {
+      return None
+    }
+    range = (range.start + leader + body.len() - body.trim_start().len())..(range.end - trailer - (body.len() - body.trim_end().len()));
+  } else {
+    range.start = content[..range.start].trim_end().len()
+  }
+  let column = |idx: usize| content[..idx].graphemes(true).count();
+  Some(Span::Chars((pos.0, column(range.start)), (pos.0, column(range.end))))
+}